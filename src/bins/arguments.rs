@@ -1,45 +1,405 @@
-use argparse::{ArgumentParser, Store, List, StoreTrue, StoreFalse, Print};
-use config::types::Config;
+use argparse::{ArgumentParser, Store, StoreConst, List, Collect, StoreTrue, StoreFalse, Print};
+use bins::error;
+use bins::settings::Settings;
+use std::env;
+use std::io::{stdout, stderr};
+use std::process;
+use std::str::FromStr;
+use bins::engines::{self, Privacy};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Command {
+  Upload,
+  Get,
+  Delete,
+  History,
+  Config,
+  Services,
+  Login,
+  Run
+}
+
+impl FromStr for Command {
+  type Err = ();
+
+  fn from_str(s: &str) -> Result<Self, ()> {
+    match s {
+      "upload" => Ok(Command::Upload),
+      "get" => Ok(Command::Get),
+      "delete" => Ok(Command::Delete),
+      "history" => Ok(Command::History),
+      "config" => Ok(Command::Config),
+      "services" => Ok(Command::Services),
+      "login" => Ok(Command::Login),
+      "run" => Ok(Command::Run),
+      _ => Err(())
+    }
+  }
+}
 
 pub struct Arguments {
+  pub command: Command,
   pub files: Vec<String>,
   pub message: String,
   pub service: String,
-  pub private: bool,
-  pub auth: bool
+  pub privacy: Privacy,
+  /// If `privacy` was asked for, with a flag or `defaults.privacy`, so services that can't make it have to refuse.
+  pub require_privacy: bool,
+  pub allow_weaker_privacy: bool,
+  pub auth: bool,
+  pub url: String,
+  pub output: String,
+  pub count: usize,
+  pub action: String,
+  pub origin: bool,
+  pub config: String,
+  pub create_config: bool,
+  pub profile: String,
+  pub recursive: bool,
+  pub include: Vec<String>,
+  pub exclude: Vec<String>,
+  pub max_size: u64,
+  pub run_command: Vec<String>,
+  pub single: bool,
+  pub tee: bool,
+  pub keep_on_interrupt: bool,
+  pub from_clipboard: bool,
+  pub copy: bool,
+  pub output_format: String,
+  pub raw: bool,
+  pub format: String,
+  pub index_format: String,
+  pub title: String,
+  pub description: String,
+  pub no_index: bool,
+  pub index_service: String,
+  pub require_all: bool,
+  pub confirm: bool,
+  pub yes: bool,
+  pub dry_run: bool,
+  pub verbose: bool,
+  privacy_flag: String,
+  auth_flag: Option<bool>,
+  copy_flag: Option<bool>
+}
+
+impl Arguments {
+  /// Fills in the values of flags that weren't given on the command line from the configuration.
+  pub fn apply_settings(&mut self, settings: &Settings) -> error::Result<()> {
+    if self.privacy_flag.is_empty() {
+      self.privacy = settings.defaults.privacy;
+      self.require_privacy = settings.defaults.require_privacy;
+    } else {
+      self.privacy = try!(self.privacy_flag.parse());
+      self.require_privacy = true;
+    }
+    self.auth = self.auth_flag.unwrap_or(settings.defaults.auth);
+    self.copy = self.copy_flag.unwrap_or(settings.defaults.copy);
+    Ok(())
+  }
 }
 
-pub fn get_arguments(config: &Config) -> Arguments {
+const DESCRIPTION: &'static str = r#"paste a file, string, or pipe to a pastebin
+
+subcommands:
+  upload     paste files, a message, or stdin (default)
+  get        download a paste
+  delete     delete a paste
+  history    list previously created pastes
+  config     show the configuration or migrate it to the XDG location
+  services   list available pastebin services
+  login      log in to a pastebin service and save the token
+  run        run a command and paste its output
+
+use `bins <subcommand> -h` for help with a subcommand, and `bins -- <file>` to paste a file named like one"#;
+
+pub fn version() -> String {
+  format!("bins {}", env!("CARGO_PKG_VERSION"))
+}
+
+/// Parses the command line. The configuration can only be read after this, since its location can be given here, so
+/// `Arguments::apply_settings` has to be called before flags with configurable defaults are used.
+pub fn get_arguments() -> Arguments {
   let mut arguments = Arguments {
+    command: Command::Upload,
     files: Vec::new(),
     message: String::from(""),
     service: String::from(""),
-    private: config.lookup_boolean_or("defaults.private", true),
-    auth: config.lookup_boolean_or("default.auth", true)
+    privacy: Privacy::Unlisted,
+    require_privacy: false,
+    allow_weaker_privacy: false,
+    auth: true,
+    url: String::from(""),
+    output: String::from(""),
+    count: 0,
+    action: String::from("show"),
+    origin: false,
+    config: String::from(""),
+    create_config: true,
+    profile: String::from(""),
+    recursive: false,
+    include: Vec::new(),
+    exclude: Vec::new(),
+    max_size: 1048576,
+    run_command: Vec::new(),
+    single: false,
+    tee: false,
+    keep_on_interrupt: false,
+    from_clipboard: false,
+    copy: false,
+    output_format: String::from("url"),
+    raw: false,
+    format: String::from(""),
+    index_format: String::from(""),
+    title: String::from(""),
+    description: String::from(""),
+    no_index: false,
+    index_service: String::from(""),
+    require_all: false,
+    confirm: false,
+    yes: false,
+    dry_run: false,
+    verbose: false,
+    privacy_flag: String::from(""),
+    auth_flag: None,
+    copy_flag: None
+  };
+  let mut args: Vec<String> = env::args().collect();
+  // only the first argument can be a subcommand, so `bins -- get` or `bins upload get` paste a file named get
+  if args.len() > 1 {
+    if let Ok(command) = args[1].parse::<Command>() {
+      arguments.command = command;
+      let command_name = args.remove(1);
+      let name = format!("{} {}", args[0], command_name);
+      args[0] = name;
+    }
+  }
+  let result = match arguments.command {
+    Command::Upload => parse_upload(&mut arguments, args),
+    Command::Get => parse_get(&mut arguments, args),
+    Command::Delete => parse_delete(&mut arguments, args),
+    Command::History => parse_history(&mut arguments, args),
+    Command::Config => parse_config(&mut arguments, args),
+    Command::Services => parse_services(&mut arguments, args),
+    Command::Login => parse_login(&mut arguments, args),
+    Command::Run => parse_run(&mut arguments, args)
   };
-  {
-    let mut ap = ArgumentParser::new();
-    ap.set_description("paste a file, string, or pipe to a pastebin");
-    ap.refer(&mut arguments.files)
-      .add_argument("files", List, "files to paste")
-      .required();
-    ap.refer(&mut arguments.service)
-      .add_option(&["-s", "--service"], Store, "pastebin service to use")
-      .required();
-    ap.refer(&mut arguments.message)
-      .add_option(&["-m", "--message"], Store, "message to paste");
-    ap.refer(&mut arguments.private)
-      .add_option(&["-p", "--private"], StoreTrue, "if the paste should be private")
-      .add_option(&["-P", "--public"], StoreFalse, "if the paste should be public");
-    ap.refer(&mut arguments.auth)
-      .add_option(&["-a", "--auth"], StoreTrue, "if authentication (like api keys and tokens) should be used")
-      .add_option(&["-A", "--anon"], StoreFalse, "if pastes should be posted without authentication");
-    ap.add_option(
-      &["-l", "--list-services"],
-      Print(String::from("gist, hastebin, pastebin, pastie")),
-      "lists pastebin services available"
-    );
-    ap.parse_args_or_exit();
+  if let Err(code) = result {
+    process::exit(code);
   }
   arguments
 }
+
+fn add_config_options<'a>(ap: &mut ArgumentParser<'a>, config: &'a mut String, create_config: &'a mut bool,
+                          profile: &'a mut String) {
+  ap.refer(config)
+    .add_option(&["--config"], Store, "configuration file to use instead of the user file")
+    .envvar("BINS_CONFIG");
+  ap.refer(create_config)
+    .add_option(&["--no-create-config"], StoreFalse, "don't create the configuration file if it doesn't exist");
+  ap.refer(profile)
+    .add_option(&["--profile"], Store, "configuration profile to use (detected from the directory by default)")
+    .envvar("BINS_PROFILE");
+}
+
+fn parse_upload(arguments: &mut Arguments, args: Vec<String>) -> Result<(), i32> {
+  let mut ap = ArgumentParser::new();
+  ap.set_description(DESCRIPTION);
+  ap.refer(&mut arguments.files)
+    .add_argument("files", List, "files to paste, optionally with lines to paste like file.rs:120-180 or file.rs:/regex/,+20");
+  ap.refer(&mut arguments.service)
+    .add_option(&["-s", "--service"], Store, "pastebin service to use, or several separated by commas to paste to each \
+                                              (defaults to BINS_SERVICE, a route or defaults.service)")
+    .envvar("BINS_SERVICE");
+  ap.refer(&mut arguments.require_all)
+    .add_option(&["--require-all"], StoreTrue, "when pasting to several services, fail if any of them fails");
+  ap.refer(&mut arguments.message)
+    .add_option(&["-m", "--message"], Store, "message to paste");
+  ap.refer(&mut arguments.from_clipboard)
+    .add_option(&["--from-clipboard"], StoreTrue, "paste the text on the clipboard");
+  ap.refer(&mut arguments.tee)
+    .add_option(&["-t", "--tee"], StoreTrue, "print stdin as it is read, then paste it");
+  ap.refer(&mut arguments.keep_on_interrupt)
+    .add_option(&["--keep-on-interrupt"], StoreTrue, "with --tee, paste what was read if Ctrl-C is pressed");
+  ap.refer(&mut arguments.recursive)
+    .add_option(&["-r", "--recursive"], StoreTrue, "paste the files in directories, skipping ignored and binary files");
+  ap.refer(&mut arguments.include)
+    .add_option(&["--include"], Collect, "only paste files in directories that match this glob (can be repeated)");
+  ap.refer(&mut arguments.exclude)
+    .add_option(&["--exclude"], Collect, "don't paste files or directories that match this glob (can be repeated)");
+  ap.refer(&mut arguments.max_size)
+    .add_option(&["--max-size"], Store, "skip files in directories larger than this many bytes (default 1048576)");
+  ap.refer(&mut arguments.privacy_flag)
+    .add_option(&["--privacy"], Store, "how hidden the paste should be: public, unlisted, private or encrypted")
+    .add_option(&["-p", "--private"], StoreConst(String::from("private")), "if the paste should be private")
+    .add_option(&["-P", "--public"], StoreConst(String::from("public")), "if the paste should be public");
+  ap.refer(&mut arguments.allow_weaker_privacy)
+    .add_option(&["--allow-weaker-privacy"], StoreTrue, "paste even if the service can't make pastes as hidden as asked");
+  ap.refer(&mut arguments.confirm)
+    .add_option(&["--confirm"], StoreTrue, "list what will be pasted and ask first, even outside of a terminal");
+  ap.refer(&mut arguments.yes)
+    .add_option(&["-y", "--yes"], StoreTrue, "paste without asking first");
+  ap.refer(&mut arguments.dry_run)
+    .add_option(&["--dry-run"], StoreTrue, "print the requests that would be sent instead of pasting anything");
+  ap.refer(&mut arguments.verbose)
+    .add_option(&["-v", "--verbose"], StoreTrue, "show the requests sent and the responses to them on stderr");
+  ap.refer(&mut arguments.auth_flag)
+    .add_option(&["-a", "--auth"], StoreConst(Some(true)), "if authentication (like api keys and tokens) should be used")
+    .add_option(&["-A", "--anon"], StoreConst(Some(false)), "if pastes should be posted without authentication");
+  ap.refer(&mut arguments.output_format)
+    .add_option(&["--output"], Store, "what to print: the url (\"url\") or everything about the paste (\"json\")");
+  ap.refer(&mut arguments.raw)
+    .add_option(&["--raw"], StoreTrue, "print the url of the raw contents instead of the paste's page");
+  ap.refer(&mut arguments.format)
+    .add_option(&["-f", "--format"], Store, "print the result with a template from the formats section, or this template");
+  ap.refer(&mut arguments.index_format)
+    .add_option(&["--index-format"], Store, "layout of the index of multi-file pastes: text, markdown, html or json");
+  ap.refer(&mut arguments.no_index)
+    .add_option(&["--no-index"], StoreTrue, "don't paste an index for multi-file pastes, print each file's url instead");
+  ap.refer(&mut arguments.index_service)
+    .add_option(&["--index-service"], Store, "pastebin service to paste the index of multi-file pastes to");
+  ap.refer(&mut arguments.title)
+    .add_option(&["--title"], Store, "title of the paste, shown in the index");
+  ap.refer(&mut arguments.description)
+    .add_option(&["--description"], Store, "description of the paste, shown in the index and used for gists");
+  ap.refer(&mut arguments.copy_flag)
+    .add_option(&["-c", "--copy"], StoreConst(Some(true)), "put the url of the paste on the clipboard")
+    .add_option(&["-C", "--no-copy"], StoreConst(Some(false)), "don't put the url of the paste on the clipboard");
+  ap.add_option(
+    &["-l", "--list-services"],
+    Print(engines::SERVICES.join(", ")),
+    "lists pastebin services available"
+  );
+  ap.add_option(&["-V", "--version"], Print(version()), "show version information");
+  add_config_options(&mut ap, &mut arguments.config, &mut arguments.create_config, &mut arguments.profile);
+  ap.parse(args, &mut stdout(), &mut stderr())
+}
+
+fn parse_get(arguments: &mut Arguments, args: Vec<String>) -> Result<(), i32> {
+  let mut ap = ArgumentParser::new();
+  ap.set_description("download a paste and print it, or save its files to a directory");
+  ap.refer(&mut arguments.url)
+    .add_argument("url", Store, "url of the paste to download")
+    .required();
+  ap.refer(&mut arguments.service)
+    .add_option(&["-s", "--service"], Store, "pastebin service the paste is on (detected from the url by default)");
+  ap.refer(&mut arguments.output)
+    .add_option(&["-o", "--output"], Store, "directory to save the paste's files to instead of printing them");
+  ap.refer(&mut arguments.auth_flag)
+    .add_option(&["-a", "--auth"], StoreConst(Some(true)), "if authentication (like api keys and tokens) should be used")
+    .add_option(&["-A", "--anon"], StoreConst(Some(false)), "if the paste should be fetched without authentication");
+  ap.refer(&mut arguments.verbose)
+    .add_option(&["-v", "--verbose"], StoreTrue, "show the requests sent and the responses to them on stderr");
+  add_config_options(&mut ap, &mut arguments.config, &mut arguments.create_config, &mut arguments.profile);
+  ap.parse(args, &mut stdout(), &mut stderr())
+}
+
+fn parse_delete(arguments: &mut Arguments, args: Vec<String>) -> Result<(), i32> {
+  let mut ap = ArgumentParser::new();
+  ap.set_description("delete a paste created with an account");
+  ap.refer(&mut arguments.url)
+    .add_argument("url", Store, "url of the paste to delete")
+    .required();
+  ap.refer(&mut arguments.service)
+    .add_option(&["-s", "--service"], Store, "pastebin service the paste is on (detected from the url by default)");
+  ap.refer(&mut arguments.verbose)
+    .add_option(&["-v", "--verbose"], StoreTrue, "show the requests sent and the responses to them on stderr");
+  add_config_options(&mut ap, &mut arguments.config, &mut arguments.create_config, &mut arguments.profile);
+  ap.parse(args, &mut stdout(), &mut stderr())
+}
+
+fn parse_history(arguments: &mut Arguments, args: Vec<String>) -> Result<(), i32> {
+  let mut ap = ArgumentParser::new();
+  ap.set_description("list previously created pastes, most recent last");
+  ap.refer(&mut arguments.count)
+    .add_option(&["-n", "--count"], Store, "only show this many of the most recent pastes");
+  add_config_options(&mut ap, &mut arguments.config, &mut arguments.create_config, &mut arguments.profile);
+  ap.parse(args, &mut stdout(), &mut stderr())
+}
+
+fn parse_config(arguments: &mut Arguments, args: Vec<String>) -> Result<(), i32> {
+  let mut ap = ArgumentParser::new();
+  ap.set_description("show the effective configuration (\"show\"), the location of the user file (\"path\"), report \
+                      problems in the configuration files (\"check\"), or move ~/.bins.cfg to the XDG location \
+                      (\"migrate\")");
+  ap.refer(&mut arguments.action)
+    .add_argument("action", Store, "what to do: show, path, check or migrate");
+  ap.refer(&mut arguments.origin)
+    .add_option(&["--origin"], StoreTrue, "show where each value came from");
+  add_config_options(&mut ap, &mut arguments.config, &mut arguments.create_config, &mut arguments.profile);
+  ap.parse(args, &mut stdout(), &mut stderr())
+}
+
+fn parse_services(arguments: &mut Arguments, args: Vec<String>) -> Result<(), i32> {
+  let mut ap = ArgumentParser::new();
+  ap.set_description("list pastebin services available");
+  add_config_options(&mut ap, &mut arguments.config, &mut arguments.create_config, &mut arguments.profile);
+  ap.parse(args, &mut stdout(), &mut stderr())
+}
+
+fn parse_login(arguments: &mut Arguments, args: Vec<String>) -> Result<(), i32> {
+  let mut ap = ArgumentParser::new();
+  ap.set_description("log in to a pastebin service and save the token for the active profile");
+  ap.refer(&mut arguments.service)
+    .add_argument("service", Store, "pastebin service to log in to (only gist is supported)")
+    .required();
+  ap.refer(&mut arguments.verbose)
+    .add_option(&["-v", "--verbose"], StoreTrue, "show the requests sent and the responses to them on stderr");
+  add_config_options(&mut ap, &mut arguments.config, &mut arguments.create_config, &mut arguments.profile);
+  ap.parse(args, &mut stdout(), &mut stderr())
+}
+
+fn parse_run(arguments: &mut Arguments, args: Vec<String>) -> Result<(), i32> {
+  let mut ap = ArgumentParser::new();
+  ap.set_description("run a command and paste what it printed, its exit status and how long it took");
+  ap.stop_on_first_argument(true);
+  ap.refer(&mut arguments.run_command)
+    .add_argument("command", List, "command to run and its arguments (put -- before it if it starts with -)")
+    .required();
+  ap.refer(&mut arguments.service)
+    .add_option(&["-s", "--service"], Store, "pastebin service to use, or several separated by commas to paste to each \
+                                              (defaults to BINS_SERVICE, a route or defaults.service)")
+    .envvar("BINS_SERVICE");
+  ap.refer(&mut arguments.require_all)
+    .add_option(&["--require-all"], StoreTrue, "when pasting to several services, fail if any of them fails");
+  ap.refer(&mut arguments.single)
+    .add_option(&["--single"], StoreTrue, "paste one document with stderr lines marked instead of separate files");
+  ap.refer(&mut arguments.privacy_flag)
+    .add_option(&["--privacy"], Store, "how hidden the paste should be: public, unlisted, private or encrypted")
+    .add_option(&["-p", "--private"], StoreConst(String::from("private")), "if the paste should be private")
+    .add_option(&["-P", "--public"], StoreConst(String::from("public")), "if the paste should be public");
+  ap.refer(&mut arguments.allow_weaker_privacy)
+    .add_option(&["--allow-weaker-privacy"], StoreTrue, "paste even if the service can't make pastes as hidden as asked");
+  ap.refer(&mut arguments.confirm)
+    .add_option(&["--confirm"], StoreTrue, "list what will be pasted and ask first, even outside of a terminal");
+  ap.refer(&mut arguments.yes)
+    .add_option(&["-y", "--yes"], StoreTrue, "paste without asking first");
+  ap.refer(&mut arguments.dry_run)
+    .add_option(&["--dry-run"], StoreTrue, "print the requests that would be sent instead of pasting anything");
+  ap.refer(&mut arguments.verbose)
+    .add_option(&["-v", "--verbose"], StoreTrue, "show the requests sent and the responses to them on stderr");
+  ap.refer(&mut arguments.auth_flag)
+    .add_option(&["-a", "--auth"], StoreConst(Some(true)), "if authentication (like api keys and tokens) should be used")
+    .add_option(&["-A", "--anon"], StoreConst(Some(false)), "if pastes should be posted without authentication");
+  ap.refer(&mut arguments.output_format)
+    .add_option(&["--output"], Store, "what to print: the url (\"url\") or everything about the paste (\"json\")");
+  ap.refer(&mut arguments.raw)
+    .add_option(&["--raw"], StoreTrue, "print the url of the raw contents instead of the paste's page");
+  ap.refer(&mut arguments.format)
+    .add_option(&["-f", "--format"], Store, "print the result with a template from the formats section, or this template");
+  ap.refer(&mut arguments.index_format)
+    .add_option(&["--index-format"], Store, "layout of the index of multi-file pastes: text, markdown, html or json");
+  ap.refer(&mut arguments.no_index)
+    .add_option(&["--no-index"], StoreTrue, "don't paste an index for multi-file pastes, print each file's url instead");
+  ap.refer(&mut arguments.index_service)
+    .add_option(&["--index-service"], Store, "pastebin service to paste the index of multi-file pastes to");
+  ap.refer(&mut arguments.title)
+    .add_option(&["--title"], Store, "title of the paste, shown in the index");
+  ap.refer(&mut arguments.description)
+    .add_option(&["--description"], Store, "description of the paste, shown in the index and used for gists");
+  ap.refer(&mut arguments.copy_flag)
+    .add_option(&["-c", "--copy"], StoreConst(Some(true)), "put the url of the paste on the clipboard")
+    .add_option(&["-C", "--no-copy"], StoreConst(Some(false)), "don't put the url of the paste on the clipboard");
+  add_config_options(&mut ap, &mut arguments.config, &mut arguments.create_config, &mut arguments.profile);
+  ap.parse(args, &mut stdout(), &mut stderr())
+}
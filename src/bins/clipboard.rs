@@ -0,0 +1,91 @@
+use bins::error::*;
+use bins::settings::ClipboardSettings;
+use std::env;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+/// A command that reads or writes the clipboard, and the environment variable that has to be set for it to work.
+struct Tool {
+  needs: Option<&'static str>,
+  program: &'static str,
+  args: &'static [&'static str]
+}
+
+const PASTE_TOOLS: &'static [Tool] = &[
+  Tool { needs: Some("WAYLAND_DISPLAY"), program: "wl-paste", args: &["--no-newline"] },
+  Tool { needs: Some("DISPLAY"), program: "xclip", args: &["-selection", "clipboard", "-out"] },
+  Tool { needs: Some("DISPLAY"), program: "xsel", args: &["--clipboard", "--output"] },
+  Tool { needs: None, program: "pbpaste", args: &[] }
+];
+
+const COPY_TOOLS: &'static [Tool] = &[
+  Tool { needs: Some("WAYLAND_DISPLAY"), program: "wl-copy", args: &[] },
+  Tool { needs: Some("DISPLAY"), program: "xclip", args: &["-selection", "clipboard", "-in"] },
+  Tool { needs: Some("DISPLAY"), program: "xsel", args: &["--clipboard", "--input"] },
+  Tool { needs: None, program: "pbcopy", args: &[] }
+];
+
+/// Gets the commands to try, either the one configured or the tools available for the current display.
+fn get_commands(configured: &Option<String>, tools: &[Tool]) -> Vec<Command> {
+  if let Some(ref c) = *configured {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(c);
+    return vec![command];
+  }
+  tools.iter()
+    .filter(|t| t.needs.map(|v| env::var_os(v).is_some()).unwrap_or(true))
+    .map(|t| {
+      let mut command = Command::new(t.program);
+      command.args(t.args);
+      command
+    })
+    .collect()
+}
+
+fn no_tool_error() -> Error {
+  "no clipboard tool was found: install wl-clipboard, xclip or xsel, or set clipboard.paste_command and \
+   clipboard.copy_command".into()
+}
+
+/// Gets the text on the clipboard.
+pub fn read(settings: &ClipboardSettings) -> Result<String> {
+  for mut command in get_commands(&settings.paste_command, PASTE_TOOLS) {
+    let output = match command.stderr(Stdio::inherit()).output() {
+      Ok(o) => o,
+      Err(ref e) if e.kind() == io::ErrorKind::NotFound => continue,
+      Err(e) => return Err(format!("could not read the clipboard: {}", e).into())
+    };
+    if !output.status.success() {
+      return Err(format!("could not read the clipboard ({})", output.status).into());
+    }
+    let text = try!(String::from_utf8(output.stdout).map_err(|_| "the clipboard does not hold text"));
+    if text.is_empty() {
+      return Err("the clipboard is empty".into());
+    }
+    return Ok(text);
+  }
+  Err(no_tool_error())
+}
+
+/// Puts `text` on the clipboard.
+pub fn write(settings: &ClipboardSettings, text: &str) -> Result<()> {
+  for mut command in get_commands(&settings.copy_command, COPY_TOOLS) {
+    let mut child = match command.stdin(Stdio::piped()).stdout(Stdio::null()).spawn() {
+      Ok(c) => c,
+      Err(ref e) if e.kind() == io::ErrorKind::NotFound => continue,
+      Err(e) => return Err(format!("could not write to the clipboard: {}", e).into())
+    };
+    {
+      let stdin = some_or_err!(child.stdin.as_mut(), "could not write to the clipboard".into());
+      try!(stdin.write_all(text.as_bytes()));
+    }
+    // closes stdin so the tool knows the text is complete
+    drop(child.stdin.take());
+    let status = try!(child.wait());
+    if !status.success() {
+      return Err(format!("could not write to the clipboard ({})", status).into());
+    }
+    return Ok(());
+  }
+  Err(no_tool_error())
+}
@@ -1,30 +1,89 @@
 extern crate config;
 
 use std::io::prelude::*;
-use std::fs::File;
-use std::path::PathBuf;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+use std::fmt;
 use std::env;
-use config::types::Config;
+use config::types::{Config, Value, ScalarValue};
 use bins::error::*;
+use bins::settings::{self, Kind, Problem};
 
 const DEFAULT_CONFIG_FILE: &'static str =
 r#"defaults = {
   /*
-   * If this is true, all pastes will be created as private or unlisted.
-   * Using the command-line option `--public` or `--private` will change this behavior.
+   * How hidden pastes must be: public, unlisted (anyone with the url can see them), private (only your account can
+   * see them) or encrypted. Services that can't make pastes this hidden refuse to paste unless
+   * `--allow-weaker-privacy` is given. If this is empty, pastes are unlisted on services that can do that, and public
+   * on the others. Older configuration files use `private = true` for that instead.
+   * Using the command-line option `--privacy`, `--public` or `--private` will change this behavior.
    */
-  private = true;
+  privacy = "";
   /*
    * If this is true, all pastes will be made to accounts or with API keys defined in this file.
    * Pastebin ignores this setting and the command-line argument, since Pastebin requires an API key to paste.
    * Using the command-line option `--auth` or `--anon` will change this behavior.
    */
   auth = true;
+  /*
+   * The service to paste to if no `--service` is given, the BINS_SERVICE environment variable is not set and no
+   * route below matches. If this is empty, one of those has to be used.
+   */
+  service = "";
+  /*
+   * The profile to use if no `--profile` is given, the BINS_PROFILE environment variable is not set and no profile
+   * matches the current directory. If this is empty, no profile is used.
+   */
+  profile = "";
+  /*
+   * How long pastes should last, for services that support it: never, 10m, 1h, 1d, 1w, 2w, 1mo, 6mo or 1y.
+   */
+  expiry = "never";
+  /*
+   * Regular expressions whose matches are replaced with [redacted] before anything is pasted.
+   */
+  redact = [];
+  /*
+   * If this is true, the url of each new paste is also put on the clipboard.
+   * Using the command-line option `--copy` or `--no-copy` will change this behavior.
+   */
+  copy = false;
+  /*
+   * Services like hastebin paste each file on its own and then paste an index linking to them. If this is set, the
+   * index is pasted to this service instead, like gist. Using `--index-service` or `--no-index` will change this.
+   */
+  index_service = "";
+  /*
+   * Services to try, in order, when the chosen one can't be reached or has a server error, like
+   * ["hastebin", "gist"]. Services that can't make pastes as hidden as `privacy` asks for are skipped.
+   */
+  fallback = [];
+  /*
+   * When to list what is about to be pasted, with warnings about secrets, large files and public pastes, and ask
+   * before pasting: always, never, or public (only when the paste will be public). The question is only asked when
+   * bins runs in a terminal. Using the command-line option `--confirm` or `--yes` will change this behavior.
+   */
+  confirm = "public";
 };
 
+/*
+ * Routes choose a service when none is given with `--service` or BINS_SERVICE. The first route that matches is used.
+ * A route matches if every file name matches its `pattern` and the files together are larger than `larger_than` bytes.
+ * Either condition may be left out.
+ */
+routes = (
+  /*
+   * { pattern = "*.diff"; service = "gist"; },
+   * { pattern = "*.log"; service = "hastebin"; },
+   * { larger_than = 1048576; service = "gist"; }
+   */
+);
+
 gist = {
   /*
-   * The username to use for gist.github.com. This is ignored if access_token is empty.
+   * The username to use for gist.github.com. This is ignored if there is no access token. If it is empty, the token is
+   * sent on its own, or with the login for the API host in ~/.netrc.
    */
   username = "";
   /*
@@ -32,6 +91,37 @@ gist = {
    * Generate a token from https://github.com/settings/tokens - only the gist permission is necessary
    */
   access_token = "";
+  /*
+   * Instead of writing the token here, it can be printed by a command, like `gh auth token` or `pass show github`, or
+   * kept in a file that only you can read. If none of these are set, the password for the API host in ~/.netrc is used.
+   */
+  access_token_command = "";
+  access_token_file = "";
+  /*
+   * The base url of the GitHub API. Change this to use GitHub Enterprise, like "https://github.example.com/api/v3".
+   */
+  url = "https://api.github.com";
+  /*
+   * The client id of a GitHub OAuth app with the device flow enabled, used by `bins login gist`. The token it gets is
+   * saved with your other configuration and used when no access token is set above.
+   */
+  client_id = "";
+  /*
+   * The base url of GitHub's OAuth endpoints. If this is empty, it is worked out from `url`.
+   */
+  oauth_url = "";
+};
+
+hastebin = {
+  /*
+   * The hastebin server to use.
+   */
+  url = "http://hastebin.com";
+  /*
+   * Hastebin pastes each file on its own, with an index paste listing them. This is the layout of the index: text,
+   * markdown, html or json. The json manifest lets `bins get` check the files it downloads.
+   */
+  index_format = "text";
 };
 
 pastebin = {
@@ -40,43 +130,636 @@ pastebin = {
    * If this is empty, all paste attempts to the pastebin service will fail.
    */
   api_key = "";
+  /*
+   * Like the gist access token, the API key can come from a command, a file only you can read or ~/.netrc.
+   */
+  api_key_command = "";
+  api_key_file = "";
+  /*
+   * The layout of the index paste for multiple files, like hastebin's.
+   */
+  index_format = "text";
+};
+
+pastie = {
+  /*
+   * The layout of the index paste for multiple files, like hastebin's.
+   */
+  index_format = "text";
+};
+
+/*
+ * Named templates for `--format`, like `bins --format slack`. Templates can use {url}, {raw_url}, {name}, {service},
+ * {size}, {expiry} and {delete_url}. Templates using {name} or {size} print a line for each file, with that file's
+ * urls.
+ */
+formats = {
+  markdown = "[{name}]({url})";
+  slack = "<{url}|{name}>";
+  html = "<a href=\"{url}\">{name}</a>";
+};
+
+clipboard = {
+  /*
+   * Commands that print the clipboard and set it from their input. If these are empty, wl-paste and wl-copy, xclip,
+   * xsel or pbpaste and pbcopy are used, whichever is available.
+   */
+  paste_command = "";
+  copy_command = "";
+};
+
+/*
+ * Profiles bundle a service, accounts and defaults. Choose one with `--profile` or BINS_PROFILE. Otherwise, the first
+ * profile whose `directories` match the current directory, or whose `remotes` match a git remote url, is used.
+ * A profile may set `service`, `privacy`, `private`, `auth`, `expiry`, `redact`, `copy`, `fallback`, `confirm`, and
+ * any key of the gist, hastebin and pastebin sections, which take the place of the values outside of the profile.
+ */
+profiles = {
+  /*
+   * work = {
+   *   service = "gist";
+   *   privacy = "unlisted";
+   *   redact = ["password=\\S+"];
+   *   directories = ["~/work"];
+   *   remotes = ["*github.example.com*"];
+   *   gist = { username = "me"; access_token = ""; url = "https://github.example.com/api/v3"; };
+   * };
+   */
 };
 "#;
 
-pub struct BinsConfiguration;
+/// Keys whose values are never printed.
+const SECRET_KEYS: &'static [&'static str] = &["gist.access_token", "pastebin.api_key"];
+
+const SYSTEM_CONFIG_PATH: &'static str = "/etc/bins.cfg";
+
+const ENVIRONMENT_PREFIX: &'static str = "BINS_";
+
+/// Gets the value of `key` in a group.
+pub fn group_get<'a>(group: &'a Value, key: &str) -> Option<&'a Value> {
+  match *group {
+    Value::Group(ref settings) => settings.get(key).map(|s| &s.value),
+    _ => None
+  }
+}
+
+pub fn group_str<'a>(group: &'a Value, key: &str) -> Option<&'a str> {
+  match group_get(group, key) {
+    Some(&Value::Svalue(ScalarValue::Str(ref s))) => Some(s),
+    _ => None
+  }
+}
+
+pub fn group_integer(group: &Value, key: &str) -> Option<i64> {
+  match group_get(group, key) {
+    Some(&Value::Svalue(ScalarValue::Integer32(i))) => Some(i as i64),
+    Some(&Value::Svalue(ScalarValue::Integer64(i))) => Some(i),
+    _ => None
+  }
+}
+
+pub fn group_boolean(group: &Value, key: &str) -> Option<bool> {
+  match group_get(group, key) {
+    Some(&Value::Svalue(ScalarValue::Boolean(b))) => Some(b),
+    _ => None
+  }
+}
+
+/// Gets the strings in an array or list, skipping values that aren't strings.
+pub fn strings(value: &Value) -> Option<Vec<String>> {
+  match *value {
+    Value::Array(ref values) | Value::List(ref values) => Some(
+      values.iter()
+        .filter_map(|v| match *v {
+          Value::Svalue(ScalarValue::Str(ref s)) => Some(s.clone()),
+          _ => None
+        })
+        .collect()
+    ),
+    _ => None
+  }
+}
+
+/// Reads a list from an environment variable, written either like in a configuration file, `["a", "b"]`, or separated
+/// by commas.
+fn environment_list(value: &str) -> Result<Vec<String>> {
+  let value = value.trim();
+  if value.starts_with('[') || value.starts_with('(') {
+    let config = try!(config::reader::from_str(&format!("value = {};", value)).map_err(|e| e.to_string()));
+    return Ok(some_or_err!(config.lookup("value").and_then(strings), "not a list".into()));
+  }
+  Ok(value.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()).map(|s| s.to_owned()).collect())
+}
+
+fn parse_boolean(value: &str) -> Option<bool> {
+  match value.to_lowercase().as_ref() {
+    "true" | "yes" | "on" | "1" => Some(true),
+    "false" | "no" | "off" | "0" => Some(false),
+    _ => None
+  }
+}
+
+pub fn group_strings(group: &Value, key: &str) -> Option<Vec<String>> {
+  group_get(group, key).and_then(strings)
+}
+
+/// Where a configuration value came from.
+pub enum Origin {
+  Default,
+  System(PathBuf),
+  User(PathBuf),
+  Project(PathBuf),
+  Environment(String)
+}
+
+impl fmt::Display for Origin {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      Origin::Default => write!(f, "built-in default"),
+      Origin::System(ref p) => write!(f, "system file {}", p.to_string_lossy()),
+      Origin::User(ref p) => write!(f, "user file {}", p.to_string_lossy()),
+      Origin::Project(ref p) => write!(f, "project file {}", p.to_string_lossy()),
+      Origin::Environment(ref v) => write!(f, "environment variable {}", v)
+    }
+  }
+}
+
+pub struct Layer {
+  pub origin: Origin,
+  pub config: Config,
+  /// The paths of the keys set in the layer, in the order they are written. `Config` keeps groups in hash maps, which
+  /// lose the order.
+  pub keys: Vec<String>
+}
+
+impl Layer {
+  /// Parses the text of a configuration file.
+  pub fn parse(origin: Origin, text: &str) -> Result<Self> {
+    Ok(Layer {
+      config: try!(config::reader::from_str(text)),
+      keys: settings::scan(text).into_iter().map(|a| a.path).collect(),
+      origin: origin
+    })
+  }
+}
+
+/// The configuration, made of layers that override each other: built-in defaults, then the system file, then the user
+/// file, then the project file, then `BINS_*` environment variables. Command-line flags override all of these.
+pub struct LayeredConfig {
+  /// File layers, lowest precedence first.
+  layers: Vec<Layer>,
+  /// Values from `BINS_SECTION_KEY` environment variables, keyed by `section.key`.
+  environment: HashMap<String, (String, Origin)>
+}
+
+impl LayeredConfig {
+  pub fn new(layers: Vec<Layer>) -> Self {
+    LayeredConfig::with_environment(layers, env::vars())
+  }
+
+  /// Makes a configuration from `layers` and the `BINS_*` variables in `variables`. `BINS_SECTION_KEY` sets
+  /// `section.key`, split at the first `_` since section names don't have one.
+  fn with_environment<I: IntoIterator<Item = (String, String)>>(layers: Vec<Layer>, variables: I) -> Self {
+    let mut environment = HashMap::new();
+    for (name, value) in variables {
+      if !name.starts_with(ENVIRONMENT_PREFIX) {
+        continue;
+      }
+      let rest = name[ENVIRONMENT_PREFIX.len()..].to_lowercase();
+      let key = match rest.find('_') {
+        Some(i) => format!("{}.{}", &rest[..i], &rest[i + 1..]),
+        None => continue
+      };
+      environment.insert(key, (value, Origin::Environment(name.clone())));
+    }
+    LayeredConfig {
+      layers: layers,
+      environment: environment
+    }
+  }
+
+  /// Looks up a value in the file layers, returning the one with the highest precedence and where it came from.
+  pub fn lookup_with_origin(&self, path: &str) -> Option<(&Value, &Origin)> {
+    self.layers.iter().rev()
+      .filter_map(|l| l.config.lookup(path).map(|v| (v, &l.origin)))
+      .next()
+  }
+
+  /// Looks up a value in every file layer that defines it, lowest precedence first.
+  pub fn lookup_all(&self, path: &str) -> Vec<&Value> {
+    self.layers.iter().filter_map(|l| l.config.lookup(path)).collect()
+  }
+
+  /// Gets the settings in the `path` group of each layer that has one, lowest precedence first, in the order they are
+  /// written.
+  pub fn lookup_all_groups(&self, path: &str) -> Vec<Vec<(&String, &Value)>> {
+    self.layers.iter()
+      .filter_map(|l| match l.config.lookup(path) {
+        Some(&Value::Group(ref settings)) => {
+          let mut group = settings.iter().map(|(name, setting)| (name, &setting.value)).collect::<Vec<_>>();
+          group.sort_by_key(|&(name, _)| l.keys.iter().position(|k| *k == format!("{}.{}", path, name)));
+          Some(group)
+        },
+        _ => None
+      })
+      .collect()
+  }
+
+  /// Checks if `path` is set by a `BINS_*` environment variable, which overrides every file and profile.
+  pub fn in_environment(&self, path: &str) -> bool {
+    self.environment.contains_key(path)
+  }
+
+  /// Gets the index of the one of `paths` that is set with the highest precedence, preferring earlier paths when
+  /// several are set in the same place.
+  pub fn highest_of(&self, paths: &[&str]) -> Option<usize> {
+    if let Some(i) = paths.iter().position(|p| self.environment.contains_key(*p)) {
+      return Some(i);
+    }
+    self.layers.iter().rev()
+      .filter_map(|l| paths.iter().position(|p| l.config.lookup(p).is_some()))
+      .next()
+  }
+
+  pub fn lookup(&self, path: &str) -> Option<&Value> {
+    self.lookup_with_origin(path).map(|(v, _)| v)
+  }
+
+  pub fn lookup_str(&self, path: &str) -> Option<&str> {
+    if let Some(&(ref value, _)) = self.environment.get(path) {
+      return Some(value);
+    }
+    match self.lookup(path) {
+      Some(&Value::Svalue(ScalarValue::Str(ref s))) => Some(s),
+      _ => None
+    }
+  }
+
+  pub fn lookup_boolean(&self, path: &str) -> Option<bool> {
+    if let Some(&(ref value, _)) = self.environment.get(path) {
+      return parse_boolean(value);
+    }
+    match self.lookup(path) {
+      Some(&Value::Svalue(ScalarValue::Boolean(b))) => Some(b),
+      _ => None
+    }
+  }
+
+  /// Gets the strings in a list. Lists set by environment variables are written like in a file or separated by
+  /// commas.
+  pub fn lookup_strings(&self, path: &str) -> Option<Vec<String>> {
+    if let Some(&(ref value, _)) = self.environment.get(path) {
+      return environment_list(value).ok();
+    }
+    self.lookup(path).and_then(strings)
+  }
+
+  /// Checks that every `BINS_*` variable for a known key can be read as the kind of value the key takes, so that none
+  /// are silently ignored.
+  pub fn check_environment(&self) -> Result<()> {
+    for (path, &(ref value, ref origin)) in &self.environment {
+      let kind = match settings::get_kind(path) {
+        Some(k) => k,
+        None => continue
+      };
+      let valid = match kind {
+        Kind::Boolean => parse_boolean(value).is_some(),
+        Kind::Integer => value.trim().parse::<i64>().is_ok(),
+        Kind::Float => value.trim().parse::<f64>().is_ok(),
+        Kind::List => environment_list(value).is_ok(),
+        Kind::Str => true,
+        Kind::Group => false
+      };
+      if !valid {
+        let message = format!("{} must be {} for {}", origin, kind.describe(), path);
+        return Err(ErrorKind::InvalidConfiguration(message).into());
+      }
+    }
+    Ok(())
+  }
+
+  pub fn lookup_boolean_or(&self, path: &str, default: bool) -> bool {
+    self.lookup_boolean(path).unwrap_or(default)
+  }
+
+  /// Gets the effective value of `path` as it would be written in a configuration file, and where it came from.
+  /// Secret values are masked.
+  pub fn describe(&self, path: &str) -> Option<(String, &Origin)> {
+    if let Some(&(ref value, ref origin)) = self.environment.get(path) {
+      let value = match settings::get_kind(path) {
+        Some(Kind::List) => match environment_list(value) {
+          Ok(values) => format!("[{}]", values.iter().map(|v| format!("{:?}", v)).collect::<Vec<_>>().join(", ")),
+          Err(_) => format!("{:?}", value)
+        },
+        Some(Kind::Boolean) => parse_boolean(value).map(|b| b.to_string()).unwrap_or_else(|| format!("{:?}", value)),
+        Some(Kind::Integer) | Some(Kind::Float) => value.trim().to_owned(),
+        _ => format!("{:?}", value)
+      };
+      return Some((mask_secret(path, value), origin));
+    }
+    self.lookup_with_origin(path).map(|(value, origin)| (describe_value(path, value), origin))
+  }
+}
+
+/// Writes `value` like it is written in a configuration file, masking it if `path` is a secret.
+pub fn describe_value(path: &str, value: &Value) -> String {
+  mask_secret(path, value_to_string(value))
+}
+
+fn mask_secret(path: &str, value: String) -> String {
+  if SECRET_KEYS.contains(&path) && value != "\"\"" {
+    String::from("\"********\"")
+  } else {
+    value
+  }
+}
+
+fn values_to_string(values: &[Value]) -> String {
+  values.iter().map(value_to_string).collect::<Vec<_>>().join(", ")
+}
+
+fn value_to_string(value: &Value) -> String {
+  match *value {
+    Value::Svalue(ScalarValue::Boolean(b)) => b.to_string(),
+    Value::Svalue(ScalarValue::Integer32(i)) => i.to_string(),
+    Value::Svalue(ScalarValue::Integer64(i)) => i.to_string(),
+    Value::Svalue(ScalarValue::Floating32(f)) => f.to_string(),
+    Value::Svalue(ScalarValue::Floating64(f)) => f.to_string(),
+    Value::Svalue(ScalarValue::Str(ref s)) => format!("{:?}", s),
+    Value::Array(ref a) => format!("[{}]", values_to_string(a)),
+    Value::List(ref l) => format!("({})", values_to_string(l)),
+    Value::Group(ref g) => {
+      let mut settings = g.iter().map(|(k, s)| format!("{} = {};", k, value_to_string(&s.value))).collect::<Vec<_>>();
+      settings.sort();
+      if settings.is_empty() {
+        String::from("{}")
+      } else {
+        format!("{{ {} }}", settings.join(" "))
+      }
+    }
+  }
+}
+
+/// Gets the user's home directory from `$HOME`.
+pub fn home_dir() -> Option<PathBuf> {
+  match env::var_os("HOME") {
+    Some(ref h) if !h.is_empty() => Some(PathBuf::from(h)),
+    _ => None
+  }
+}
+
+/// Gets an XDG base directory from `variable`, falling back to `fallback` in the home directory. Relative paths in
+/// the variable are ignored, as the specification requires.
+pub fn xdg_dir(variable: &str, fallback: &str) -> Option<PathBuf> {
+  if let Some(dir) = env::var_os(variable) {
+    let path = PathBuf::from(dir);
+    if path.is_absolute() {
+      return Some(path);
+    }
+  }
+  home_dir().map(|h| h.join(fallback))
+}
+
+pub struct BinsConfiguration {
+  /// The user file given with `--config` or `BINS_CONFIG`, if any.
+  path: Option<PathBuf>,
+  /// If the user file should be created with the default configuration when it doesn't exist.
+  create: bool
+}
 
 impl BinsConfiguration {
-  pub fn new() -> Self {
-    BinsConfiguration { }
+  pub fn new(path: Option<PathBuf>, create: bool) -> Self {
+    BinsConfiguration {
+      path: path,
+      create: create
+    }
+  }
+
+  /// Gets the path of the configuration file used before bins followed the XDG base directory specification.
+  pub fn get_legacy_config_path(&self) -> Option<PathBuf> {
+    home_dir().map(|h| h.join(".bins.cfg"))
+  }
+
+  /// Moves the legacy `~/.bins.cfg` to the XDG location, returning both paths.
+  pub fn migrate(&self) -> Result<(PathBuf, PathBuf)> {
+    let legacy = some_or_err!(self.get_legacy_config_path(), "could not get path to the legacy configuration file".into());
+    let path = some_or_err!(self.get_xdg_config_path(), "could not get path to the configuration file".into());
+    if !legacy.is_file() {
+      return Err(format!("there is no configuration file at {} to migrate", legacy.to_string_lossy()).into());
+    }
+    if path.exists() {
+      return Err(format!("{} already exists", path.to_string_lossy()).into());
+    }
+    if let Some(parent) = path.parent() {
+      try!(fs::create_dir_all(parent));
+    }
+    try!(fs::rename(&legacy, &path).or_else(|_| fs::copy(&legacy, &path).and_then(|_| fs::remove_file(&legacy))));
+    Ok((legacy, path))
+  }
+
+  /// Finds the closest `.bins.cfg` in the current directory or its parents, ignoring the user file.
+  fn get_project_config_path(&self) -> Option<PathBuf> {
+    let ignored = vec![self.get_config_path(), self.get_legacy_config_path()];
+    let mut dir = match env::current_dir() {
+      Ok(d) => d,
+      Err(_) => return None
+    };
+    loop {
+      let path = dir.join(".bins.cfg");
+      if path.is_file() && !ignored.contains(&Some(path.clone())) {
+        return Some(path);
+      }
+      if !dir.pop() {
+        return None;
+      }
+    }
+  }
+
+  fn create_config(&self, path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+      try!(fs::create_dir_all(parent));
+    }
+    let mut file = try!(File::create(path));
+    try!(file.write_all(DEFAULT_CONFIG_FILE.as_bytes()));
+    Ok(())
+  }
+
+  /// Gets the configuration files that exist, without creating the user file.
+  pub fn get_files(&self) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let system = PathBuf::from(SYSTEM_CONFIG_PATH);
+    if system.exists() {
+      files.push(system);
+    }
+    if let Some(path) = self.get_config_path() {
+      if path.exists() {
+        files.push(path);
+      }
+    }
+    if let Some(project) = self.get_project_config_path() {
+      files.push(project);
+    }
+    files
+  }
+
+  /// Checks a configuration file for syntax errors, unknown keys and values of the wrong kind, and the project file for
+  /// keys it isn't trusted with.
+  pub fn check(&self, path: &Path) -> Result<Vec<Problem>> {
+    if path.is_dir() || !path.is_file() {
+      return Err(format!("configuration file {} exists, but is not a valid file", path.to_string_lossy()).into())
+    }
+    let mut text = String::new();
+    try!(try!(File::open(path)).read_to_string(&mut text));
+    if let Err(e) = config::reader::from_str(&text) {
+      return Ok(vec![Problem { line: 0, message: e.to_string() }]);
+    }
+    let mut problems = settings::validate(&text);
+    if self.get_project_config_path().as_ref().map(|p| p == path).unwrap_or(false) {
+      problems.extend(settings::validate_project(&text));
+      problems.sort_by_key(|p| p.line);
+    }
+    Ok(problems)
+  }
+
+  fn read_layer(&self, path: &Path, origin: Origin) -> Result<Layer> {
+    let problems = try!(self.check(path));
+    if !problems.is_empty() {
+      let messages = problems.iter()
+        .map(|p| format!("{}:{}: {}", path.to_string_lossy(), p.line, p.message))
+        .collect::<Vec<_>>();
+      let message = format!("invalid configuration file (see `bins config check`)\n{}", messages.join("\n"));
+      return Err(ErrorKind::InvalidConfiguration(message).into());
+    }
+    let mut text = String::new();
+    try!(try!(File::open(path)).read_to_string(&mut text));
+    Layer::parse(origin, &text)
   }
 }
 
 pub trait Configurable {
-  fn parse_config(&self) -> Result<Config>;
+  fn parse_config(&self) -> Result<LayeredConfig>;
+
+  /// Gets the path of the user file in the XDG configuration directory, `$XDG_CONFIG_HOME/bins/bins.cfg`.
+  fn get_xdg_config_path(&self) -> Option<PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME", ".config").map(|d| d.join("bins").join("bins.cfg"))
+  }
 
   fn get_config_path(&self) -> Option<PathBuf> {
-    let mut home = match env::home_dir() {
-      Some(p) => p,
-      None => return None
-    };
-    home.push(".bins.cfg");
-    Some(home)
+    self.get_xdg_config_path()
   }
 }
 
 impl Configurable for BinsConfiguration {
-  fn parse_config(&self) -> Result<Config> {
+  /// Gets the path of the user file: the one given with `--config` or `BINS_CONFIG`, or the XDG one. If only the
+  /// legacy `~/.bins.cfg` exists, that is used until it is migrated.
+  fn get_config_path(&self) -> Option<PathBuf> {
+    if let Some(ref p) = self.path {
+      return Some(p.clone());
+    }
+    let xdg = self.get_xdg_config_path();
+    if let Some(ref p) = xdg {
+      if p.exists() {
+        return xdg.clone();
+      }
+    }
+    match self.get_legacy_config_path() {
+      Some(ref l) if l.exists() => Some(l.clone()),
+      _ => xdg
+    }
+  }
+
+  fn parse_config(&self) -> Result<LayeredConfig> {
+    let mut layers = vec![try!(Layer::parse(Origin::Default, DEFAULT_CONFIG_FILE))];
+    let system = PathBuf::from(SYSTEM_CONFIG_PATH);
+    if system.exists() {
+      layers.push(try!(self.read_layer(&system, Origin::System(system.clone()))));
+    }
     let path = match self.get_config_path() {
       Some(p) => p,
       None => return Err("could not get path to the configuration file".into())
     };
-    if !&path.exists() {
-      let mut file = try!(File::create(&path));
-      try!(file.write_all(DEFAULT_CONFIG_FILE.as_bytes()));
+    // a user file that can't be created (like in a read-only home directory) is not an error, since the built-in
+    // defaults are still there
+    let exists = path.exists() || (self.create && self.create_config(&path).is_ok());
+    if exists {
+      layers.push(try!(self.read_layer(&path, Origin::User(path.clone()))));
+    } else if self.path.is_some() {
+      let message = format!("configuration file {} does not exist", path.to_string_lossy());
+      return Err(ErrorKind::InvalidConfiguration(message).into());
+    }
+    if let Some(project) = self.get_project_config_path() {
+      layers.push(try!(self.read_layer(&project, Origin::Project(project.clone()))));
     }
-    if (&path).is_dir() || !&path.is_file() {
-      return Err("configuration file exists, but is not a valid file".into())
+    let config = LayeredConfig::new(layers);
+    try!(config.check_environment());
+    Ok(config)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{environment_list, Layer, LayeredConfig, Origin};
+  use bins::error::ErrorKind;
+
+  fn variables(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+    pairs.iter().map(|&(k, v)| (k.to_owned(), v.to_owned())).collect()
+  }
+
+  #[test]
+  fn environment_variables_are_split_at_the_first_underscore() {
+    let config = LayeredConfig::with_environment(Vec::new(), variables(&[
+      ("BINS_GIST_ACCESS_TOKEN", "abc"),
+      ("BINS_PASTEBIN_API_KEY_COMMAND", "pass show pastebin"),
+      ("BINS_SERVICE", "gist"),
+      ("HOME", "/home/me")
+    ]));
+    assert_eq!(config.lookup_str("gist.access_token"), Some("abc"));
+    assert_eq!(config.lookup_str("pastebin.api_key_command"), Some("pass show pastebin"));
+    assert!(!config.in_environment("service"));
+    assert!(!config.in_environment("home"));
+  }
+
+  #[test]
+  fn environment_variables_override_files() {
+    let file = "defaults = { copy = false; fallback = [\"gist\"]; };";
+    let layers = vec![Layer::parse(Origin::Default, file).unwrap()];
+    let config = LayeredConfig::with_environment(layers, variables(&[("BINS_DEFAULTS_COPY", "yes")]));
+    assert_eq!(config.lookup_boolean("defaults.copy"), Some(true));
+    assert_eq!(config.lookup_strings("defaults.fallback"), Some(vec![String::from("gist")]));
+    assert_eq!(config.describe("defaults.fallback").map(|(v, _)| v), Some(String::from("[\"gist\"]")));
+    assert_eq!(config.describe("defaults.copy").map(|(v, o)| (v, o.to_string())),
+               Some((String::from("true"), String::from("environment variable BINS_DEFAULTS_COPY"))));
+  }
+
+  #[test]
+  fn environment_lists_are_written_like_files_or_separated_by_commas() {
+    let expected = vec![String::from("a"), String::from("b c")];
+    assert_eq!(environment_list("a, b c,").ok(), Some(expected.clone()));
+    assert_eq!(environment_list("[\"a\", \"b c\"]").ok(), Some(expected.clone()));
+    assert_eq!(environment_list(" (\"a\", \"b c\") ").ok(), Some(expected));
+    assert_eq!(environment_list("").ok(), Some(Vec::new()));
+    assert!(environment_list("[\"a\"").is_err());
+  }
+
+  #[test]
+  fn environment_values_must_be_the_kind_their_key_takes() {
+    let valid = variables(&[
+      ("BINS_DEFAULTS_COPY", "off"),
+      ("BINS_DEFAULTS_REDACT", "[\"token=\\\\S+\"]"),
+      ("BINS_ROUTES_LARGER_THAN", "100"),
+      ("BINS_UNKNOWN_KEY", "anything")
+    ]);
+    assert!(LayeredConfig::with_environment(Vec::new(), valid).check_environment().is_ok());
+    for &(name, value) in &[("BINS_DEFAULTS_COPY", "maybe"), ("BINS_DEFAULTS_FALLBACK", "[\"gist\""),
+                            ("BINS_ROUTES_LARGER_THAN", "big")] {
+      let config = LayeredConfig::with_environment(Vec::new(), variables(&[(name, value)]));
+      match config.check_environment() {
+        Err(e) => match *e.kind() {
+          ErrorKind::InvalidConfiguration(_) => {},
+          _ => panic!("{} gave the wrong kind of error", name)
+        },
+        Ok(_) => panic!("{}={} should be invalid", name, value)
+      }
     }
-    Ok(try!(config::reader::from_file(path.as_path())))
   }
 }
@@ -0,0 +1,146 @@
+use bins::error::*;
+use bins::{Bins, PasteFile};
+use bins::engines::{self, Privacy};
+use bins::selection;
+use regex::Regex;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+/// When to ask before pasting.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Confirm {
+  Always,
+  Never,
+  /// Only when the paste will be public on at least one service.
+  Public
+}
+
+pub const CONFIRMS: &'static [&'static str] = &["always", "never", "public"];
+
+impl FromStr for Confirm {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    match s {
+      "always" => Ok(Confirm::Always),
+      "never" => Ok(Confirm::Never),
+      "public" => Ok(Confirm::Public),
+      _ => Err(format!("unknown confirm setting \"{}\" (use {})", s, CONFIRMS.join(", ")).into())
+    }
+  }
+}
+
+/// Files larger than this many bytes are warned about. This is the default `--max-size`.
+const LARGE_FILE: usize = 1048576;
+
+/// Patterns for secrets that are commonly pasted by accident, and what they are.
+const SECRETS: &'static [(&'static str, &'static str)] = &[
+  (r"-----BEGIN [A-Z ]*PRIVATE KEY-----", "a private key"),
+  (r"\bAKIA[0-9A-Z]{16}\b", "an AWS access key"),
+  (r"\bgh[pousr]_[A-Za-z0-9]{36}\b", "a GitHub token"),
+  (r"\bxox[abprs]-[A-Za-z0-9-]{10,}", "a Slack token"),
+  (r"(?i)\b(password|passwd|secret|api_?key|access_?token)\s*[=:]\s*\S+", "a password or key")
+];
+
+/// Checks if stderr is a terminal, which is where the prompt is shown.
+#[cfg(unix)]
+fn stderr_is_tty() -> bool {
+  use libc;
+  unsafe { libc::isatty(libc::STDERR_FILENO) == 1 }
+}
+
+#[cfg(not(unix))]
+fn stderr_is_tty() -> bool {
+  false
+}
+
+/// Reads the answer from the terminal, since stdin may be what is being pasted.
+#[cfg(unix)]
+fn read_answer() -> Result<String> {
+  use std::fs::File;
+  use std::io::{BufRead, BufReader};
+  let tty = try!(File::open("/dev/tty").map_err(|e| format!("could not ask for confirmation: {}", e)));
+  let mut answer = String::new();
+  try!(BufReader::new(tty).read_line(&mut answer));
+  Ok(answer)
+}
+
+#[cfg(not(unix))]
+fn read_answer() -> Result<String> {
+  let mut answer = String::new();
+  try!(io::stdin().read_line(&mut answer));
+  Ok(answer)
+}
+
+/// Gets what each file seems to contain that shouldn't be pasted, like `a private key`.
+fn find_secrets(file: &PasteFile) -> Vec<&'static str> {
+  SECRETS.iter()
+    .filter(|&&(pattern, _)| Regex::new(pattern).map(|r| r.is_match(&file.data)).unwrap_or(false))
+    .map(|&(_, name)| name)
+    .collect()
+}
+
+/// Shows what is about to be pasted to `services` and asks whether to go on, depending on `--yes`, `--confirm` and
+/// `defaults.confirm`. The prompt is skipped when stderr isn't a terminal unless `--confirm` is given. Answering
+/// anything but yes is an error, so nothing is pasted.
+pub fn confirm(bins: &Bins, services: &[String], to_paste: &[PasteFile]) -> Result<()> {
+  if bins.arguments.yes {
+    return Ok(());
+  }
+  let mut privacies = Vec::new();
+  for service in services {
+    let engine = try!(bins.get_engine_by_service(service));
+    if !bins.arguments.allow_weaker_privacy {
+      // pastes that would be refused are reported before asking
+      try!(engines::check_privacy(bins, service, &*engine));
+    }
+    privacies.push(engines::get_privacy(bins, &*engine));
+  }
+  let public = privacies.contains(&Privacy::Public);
+  if !bins.arguments.confirm {
+    match bins.settings.defaults.confirm {
+      Confirm::Never => return Ok(()),
+      Confirm::Public if !public => return Ok(()),
+      _ => {}
+    }
+    if !stderr_is_tty() {
+      return Ok(());
+    }
+  }
+  let noun = if to_paste.len() == 1 { "file" } else { "files" };
+  let mut summary = format!("About to paste {} {}:\n", to_paste.len(), noun);
+  let width = to_paste.iter().map(|f| f.name.chars().count()).max().unwrap_or(0);
+  for file in to_paste {
+    // what is uploaded, including the comment on selections
+    let data = selection::add_header(file).data;
+    summary.push_str(&format!("  {:<width$}  {} bytes, {} lines\n", file.name, data.len(), data.lines().count(),
+                              width = width));
+  }
+  for (service, privacy) in services.iter().zip(privacies.iter()) {
+    summary.push_str(&format!("Service: {} ({})\n", service, privacy));
+  }
+  if services.len() == 1 && !bins.settings.defaults.fallback.is_empty() {
+    summary.push_str(&format!("Fallback: {}\n", bins.settings.defaults.fallback.join(", ")));
+  }
+  summary.push_str(&format!("Auth: {}\n", if bins.arguments.auth { "yes" } else { "no" }));
+  for file in to_paste {
+    for secret in find_secrets(file) {
+      summary.push_str(&format!("warning: {} looks like it contains {}\n", file.name, secret));
+    }
+    if file.data.len() > LARGE_FILE {
+      summary.push_str(&format!("warning: {} is larger than {} bytes\n", file.name, LARGE_FILE));
+    }
+  }
+  if public {
+    summary.push_str("warning: the paste will be public\n");
+  }
+  summary.push_str("Paste? [y/N] ");
+  let mut stderr = io::stderr();
+  try!(stderr.write_all(summary.as_bytes()));
+  try!(stderr.flush());
+  let answer = try!(read_answer());
+  match answer.trim().to_lowercase().as_ref() {
+    "y" | "yes" => Ok(()),
+    _ => Err("aborting the paste".into())
+  }
+}
@@ -0,0 +1,280 @@
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use bins::error::*;
+use bins::configuration;
+
+/// Gets the path a secret saved by bins, like the token from `bins login`, is kept at. Each profile has its own.
+pub fn get_store_path(profile: Option<&str>, name: &str) -> Option<PathBuf> {
+  configuration::xdg_dir("XDG_CONFIG_HOME", ".config")
+    .map(|d| d.join("bins").join("credentials").join(profile.unwrap_or("default")).join(name))
+}
+
+/// A secret, like an access token, that can be written in the configuration, printed by a command, read from a file,
+/// saved by bins or found in `~/.netrc`. It is only looked up when needed and then kept for the rest of the run.
+pub struct Credential {
+  /// The configuration key of the secret, used in error messages.
+  name: String,
+  value: Option<String>,
+  command: Option<String>,
+  file: Option<String>,
+  /// Where bins saves this secret.
+  stored: Option<PathBuf>,
+  /// The host to look for in `~/.netrc`.
+  host: Option<String>,
+  cache: Mutex<Option<Option<String>>>
+}
+
+impl Credential {
+  pub fn new(name: &str, value: Option<String>, command: Option<String>, file: Option<String>, stored: Option<PathBuf>,
+             host: Option<String>) -> Self {
+    Credential {
+      name: name.to_owned(),
+      value: value,
+      command: command,
+      file: file,
+      stored: stored,
+      host: host,
+      cache: Mutex::new(None)
+    }
+  }
+
+  /// Gets the secret, trying the value in the configuration, then the command, then the file, then the one saved by
+  /// bins, then `~/.netrc`.
+  pub fn get(&self) -> Result<Option<String>> {
+    // held during the lookup, so pasting to several services at once still only runs the command once
+    let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(ref cached) = *cache {
+      return Ok(cached.clone());
+    }
+    let secret = try!(self.lookup());
+    *cache = Some(secret.clone());
+    Ok(secret)
+  }
+
+  fn lookup(&self) -> Result<Option<String>> {
+    if let Some(ref value) = self.value {
+      return Ok(Some(value.clone()));
+    }
+    if let Some(ref command) = self.command {
+      return self.run_command(command).map(Some);
+    }
+    if let Some(ref file) = self.file {
+      let text = try!(read_private_file(&self.name, Path::new(file)));
+      return Ok(Some(text.trim().to_owned()));
+    }
+    if let Some(ref stored) = self.stored {
+      if stored.is_file() {
+        let text = try!(read_private_file(&self.name, stored));
+        return Ok(Some(text.trim().to_owned()));
+      }
+    }
+    if let Some(ref host) = self.host {
+      return Ok(try!(Netrc::find(host)).and_then(|m| m.password));
+    }
+    Ok(None)
+  }
+
+  /// Saves `secret` where bins keeps this secret, readable only by the current user, and uses it for the rest of the
+  /// run. Returns the path it was saved to.
+  pub fn store(&self, secret: &str) -> Result<PathBuf> {
+    let path = some_or_err!(self.stored.clone(), format!("could not get a path to save {} to", self.name).into());
+    if let Some(parent) = path.parent() {
+      try!(fs::create_dir_all(parent));
+    }
+    let mut file = try!(open_private_file(&path));
+    try!(file.write_all(secret.as_bytes()));
+    *self.cache.lock().unwrap_or_else(|e| e.into_inner()) = Some(Some(secret.to_owned()));
+    Ok(path)
+  }
+
+  fn run_command(&self, command: &str) -> Result<String> {
+    let output = try!(
+      Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        // the paste may be piped in, and it isn't the command's to read
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| format!("could not run {}_command: {}", self.name, e))
+    );
+    // the output is the secret, so it is never part of an error
+    if !output.status.success() {
+      return Err(format!("{}_command failed ({})", self.name, output.status).into());
+    }
+    let secret = try!(String::from_utf8(output.stdout).map_err(|_| format!("{}_command printed invalid UTF-8", self.name)));
+    let secret = secret.trim();
+    if secret.is_empty() {
+      return Err(format!("{}_command printed nothing", self.name).into());
+    }
+    Ok(secret.to_owned())
+  }
+}
+
+impl fmt::Debug for Credential {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "Credential({})", self.name)
+  }
+}
+
+/// Reads a file holding secrets, refusing files that other users can read.
+fn read_private_file(name: &str, path: &Path) -> Result<String> {
+  let file_name = path.to_string_lossy();
+  let mut file = try!(File::open(path).map_err(|e| format!("could not open {} for {}: {}", file_name, name, e)));
+  if try!(is_readable_by_others(&file)) {
+    return Err(format!("refusing to read {} for {}: other users can read it (chmod go-r to fix)", file_name, name).into());
+  }
+  let mut text = String::new();
+  try!(file.read_to_string(&mut text).map_err(|e| format!("could not read {} for {}: {}", file_name, name, e)));
+  Ok(text)
+}
+
+#[cfg(unix)]
+fn open_private_file(path: &Path) -> Result<File> {
+  use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+  let file = try!(OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(path));
+  // the mode is only used when creating the file
+  try!(fs::set_permissions(path, fs::Permissions::from_mode(0o600)));
+  Ok(file)
+}
+
+#[cfg(not(unix))]
+fn open_private_file(path: &Path) -> Result<File> {
+  Ok(try!(OpenOptions::new().write(true).create(true).truncate(true).open(path)))
+}
+
+/// Checks if users other than the owner can read `file`. Groups often hold more than one user, so a file its group can
+/// read isn't private either.
+#[cfg(unix)]
+fn is_readable_by_others(file: &File) -> Result<bool> {
+  use std::os::unix::fs::PermissionsExt;
+  let metadata = try!(file.metadata());
+  Ok(metadata.permissions().mode() & 0o044 != 0)
+}
+
+#[cfg(not(unix))]
+#[allow(unused_variables)]
+fn is_readable_by_others(file: &File) -> Result<bool> {
+  Ok(false)
+}
+
+pub struct NetrcMachine {
+  pub login: Option<String>,
+  pub password: Option<String>
+}
+
+pub struct Netrc;
+
+impl Netrc {
+  /// Finds the entry for `host` in `~/.netrc`, falling back to the `default` entry.
+  pub fn find(host: &str) -> Result<Option<NetrcMachine>> {
+    let path = match configuration::home_dir() {
+      Some(h) => h.join(".netrc"),
+      None => return Ok(None)
+    };
+    if !path.is_file() {
+      return Ok(None);
+    }
+    let text = try!(read_private_file("netrc", &path));
+    Ok(Netrc::parse(&text, host))
+  }
+
+  fn parse(text: &str, host: &str) -> Option<NetrcMachine> {
+    let mut tokens = text.split_whitespace();
+    let mut found: Option<NetrcMachine> = None;
+    let mut default: Option<NetrcMachine> = None;
+    // which entry the tokens belong to: 0 for none, 1 for the host, 2 for default
+    let mut current = 0;
+    while let Some(token) = tokens.next() {
+      match token {
+        "machine" => {
+          if found.is_some() {
+            break;
+          }
+          current = if tokens.next() == Some(host) { 1 } else { 0 };
+          if current == 1 {
+            found = Some(NetrcMachine { login: None, password: None });
+          }
+        },
+        "default" => {
+          current = 2;
+          default = Some(NetrcMachine { login: None, password: None });
+        },
+        "login" | "password" => {
+          let value = tokens.next().map(|s| s.to_owned());
+          let machine = match current {
+            1 => found.as_mut(),
+            2 => default.as_mut(),
+            _ => None
+          };
+          if let Some(m) = machine {
+            if token == "login" {
+              m.login = value;
+            } else {
+              m.password = value;
+            }
+          }
+        },
+        "account" => {
+          tokens.next();
+        },
+        _ => {}
+      }
+    }
+    found.or(default)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::Netrc;
+
+  #[test]
+  fn netrc_finds_the_machine() {
+    let text = "machine api.github.com login octocat password abc\nmachine pastebin.com password def\n";
+    let machine = Netrc::parse(text, "pastebin.com").unwrap();
+    assert_eq!(machine.login, None);
+    assert_eq!(machine.password, Some(String::from("def")));
+  }
+
+  #[test]
+  fn netrc_skips_accounts_and_other_machines_values() {
+    let text = "machine a.com login password password login\nmachine b.com account x login me password abc\n";
+    let machine = Netrc::parse(text, "b.com").unwrap();
+    assert_eq!(machine.login, Some(String::from("me")));
+    assert_eq!(machine.password, Some(String::from("abc")));
+  }
+
+  #[test]
+  fn netrc_falls_back_to_default() {
+    let text = "machine a.com password abc\ndefault login anonymous password def\n";
+    let machine = Netrc::parse(text, "b.com").unwrap();
+    assert_eq!(machine.login, Some(String::from("anonymous")));
+    assert_eq!(machine.password, Some(String::from("def")));
+    assert_eq!(Netrc::parse(text, "a.com").unwrap().password, Some(String::from("abc")));
+    assert!(Netrc::parse("machine a.com password abc\n", "b.com").is_none());
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn private_files_can_only_be_read_by_their_owner() {
+    use super::read_private_file;
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::os::unix::fs::PermissionsExt;
+    let path = env::temp_dir().join("bins-credentials-test");
+    File::create(&path).unwrap().write_all(b"abc\n").unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+    assert_eq!(read_private_file("test", &path).ok(), Some(String::from("abc\n")));
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+    assert!(read_private_file("test", &path).is_err());
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o604)).unwrap();
+    assert!(read_private_file("test", &path).is_err());
+    fs::remove_file(&path).unwrap();
+  }
+}
@@ -0,0 +1,96 @@
+use bins::error::*;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Everything after this line in the file is ignored, like in `git commit -v`, so pastes can have lines starting with
+/// `#`.
+const SCISSORS: &'static str = "# ------------------------ >8 ------------------------";
+
+/// Checks if stdin is a terminal, in which case there is nothing piped to paste.
+#[cfg(unix)]
+pub fn stdin_is_tty() -> bool {
+  use libc;
+  unsafe { libc::isatty(libc::STDIN_FILENO) == 1 }
+}
+
+#[cfg(not(unix))]
+pub fn stdin_is_tty() -> bool {
+  false
+}
+
+/// Gets the editor to use: `$VISUAL`, then `$EDITOR`, then `vi`.
+fn get_editor() -> String {
+  env::var("VISUAL").ok()
+    .or_else(|| env::var("EDITOR").ok())
+    .and_then(|e| if e.trim().is_empty() { None } else { Some(e) })
+    .unwrap_or_else(|| String::from("vi"))
+}
+
+fn get_temp_path() -> PathBuf {
+  let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() * 1000000000 + d.subsec_nanos() as u64).unwrap_or(0);
+  env::temp_dir().join(format!("bins-{}.txt", now))
+}
+
+/// Creates the file the paste is written in. Only its owner can read it, since it may hold anything.
+#[cfg(unix)]
+fn create_temp_file(path: &Path) -> Result<File> {
+  use std::os::unix::fs::OpenOptionsExt;
+  Ok(try!(OpenOptions::new().write(true).create_new(true).mode(0o600).open(path)))
+}
+
+#[cfg(not(unix))]
+fn create_temp_file(path: &Path) -> Result<File> {
+  Ok(try!(OpenOptions::new().write(true).create_new(true).open(path)))
+}
+
+/// Opens the editor on an empty file with `header` in comments below it, returning what was written above the
+/// comments. Empty pastes are an error.
+pub fn compose(header: &[String]) -> Result<String> {
+  let path = get_temp_path();
+  {
+    let mut file = try!(create_temp_file(&path));
+    let mut template = format!("\n{}\n# Do not modify or remove the line above.\n# Everything below it will be ignored.\n#\n",
+                               SCISSORS);
+    for line in header {
+      template.push_str(&format!("# {}\n", line));
+    }
+    template.push_str("#\n# Save an empty paste to abort.\n");
+    try!(file.write_all(template.as_bytes()));
+  }
+  let result = edit(&path);
+  let _ = fs::remove_file(&path);
+  let text = try!(result);
+  let text = match text.find(SCISSORS) {
+    Some(i) => &text[..i],
+    None => &text[..]
+  };
+  let text = text.trim_right();
+  if text.trim().is_empty() {
+    return Err("aborting the paste because it is empty".into());
+  }
+  Ok(format!("{}\n", text))
+}
+
+fn edit(path: &PathBuf) -> Result<String> {
+  let editor = get_editor();
+  // through the shell, so editors with arguments like "code --wait" work
+  let status = try!(
+    Command::new("sh")
+      .arg("-c")
+      .arg(format!("{} \"$1\"", editor))
+      .arg("sh")
+      .arg(path)
+      .status()
+      .map_err(|e| format!("could not start {}: {}", editor, e))
+  );
+  if !status.success() {
+    return Err(format!("aborting the paste because {} failed ({})", editor, status).into());
+  }
+  let mut text = String::new();
+  try!(try!(File::open(path)).read_to_string(&mut text));
+  Ok(text)
+}
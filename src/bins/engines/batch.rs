@@ -1,13 +1,19 @@
 use bins::error::*;
 use bins::{Bins, PasteFile};
-use std::iter::repeat;
-use hyper::client::Client;
+use bins::engines::{self, Engine, UploadResult, UploadedFile};
+use bins::http::Request;
+use bins::selection;
+use bins::engines::index::{self, IndexFormat, IndexHeader};
 use hyper::client::Response;
 use hyper::header::Headers;
-use std::io::Read;
+use hyper::method::Method;
+use std::io::{self, Write};
 use hyper::status::StatusCode;
+use url::Url;
 
 pub struct BatchUpload {
+  /// The name of the service, for upload results.
+  pub service: &'static str,
   pub url: String,
   pub headers: Headers,
   pub url_producer: Box<ProducesUrl>,
@@ -15,7 +21,8 @@ pub struct BatchUpload {
 }
 
 pub trait ProducesUrl {
-  fn produce_url(&self, bins: &Bins, res: Response, data: String) -> Result<String>;
+  /// Describes the paste made for `file` from the service's response, which was `data`.
+  fn produce_url(&self, bins: &Bins, file: &PasteFile, res: Response, data: String) -> Result<UploadedFile>;
 }
 
 pub trait ProducesBody {
@@ -23,63 +30,146 @@ pub trait ProducesBody {
 }
 
 pub trait UploadsBatches {
-  fn real_upload(&self, bins: &Bins, data: &PasteFile) -> Result<String>;
+  fn service(&self) -> &'static str;
 
-  fn upload(&self, bins: &Bins, data: &Vec<PasteFile>) -> Result<String> {
+  fn real_upload(&self, bins: &Bins, data: &PasteFile) -> Result<UploadedFile>;
+
+  fn upload(&self, bins: &Bins, data: &[PasteFile]) -> Result<UploadResult> {
+    // selections say where they came from in a comment
+    let data = &data.iter().map(selection::add_header).collect::<Vec<_>>();
     if data.len() < 2 {
-      return self.real_upload(bins, &data[0]);
+      let file = try!(self.real_upload(bins, &data[0]));
+      return Ok(UploadResult {
+        service: self.service().to_owned(),
+        url: file.url.clone(),
+        raw_url: file.raw_url.clone(),
+        id: file.id.clone(),
+        delete_token: None,
+        delete_url: None,
+        index_service: None,
+        expiry: None,
+        files: vec![file]
+      });
     }
-    let wrapped_urls = data.iter().map(|f| self.real_upload(bins, f)).map(|r| r.map_err(|e| e.iter().map(|x| x.to_string()).collect::<Vec<_>>().join("\n"))).collect::<Vec<_>>();
-    for url in wrapped_urls.iter().cloned() {
-      if url.is_err() {
-        return Err(url.err().unwrap().into());
+    let format = try!(get_index_format(bins, self.service()));
+    if !bins.arguments.no_index {
+      if let Some(ref service) = get_index_service(bins) {
+        try!(engines::check_privacy(bins, service, &*try!(bins.get_engine_by_service(service))));
       }
     }
-    let urls = wrapped_urls.iter().cloned().map(|r| r.unwrap());
-    let mut index = self.generate_index(data);
-    let mut number = 1;
-    for url in urls {
-      let replace = String::from("<url") + &number.to_string() + ">";
-      index = index.replace(&replace, url.as_ref());
-      number += 1;
+    // stops at the first failure, keeping its error so an unavailable service can be told apart
+    let mut files = Vec::new();
+    for file in data {
+      files.push(try!(self.real_upload(bins, file)));
+    }
+    if bins.arguments.no_index {
+      return Ok(UploadResult {
+        service: self.service().to_owned(),
+        // there is nothing to share for the whole upload, only its files
+        url: String::new(),
+        raw_url: None,
+        id: None,
+        delete_token: None,
+        delete_url: None,
+        index_service: None,
+        expiry: None,
+        files: files
+      });
     }
-    let index_url = try!(self.real_upload(bins, &PasteFile { name: String::from("index"), data: index }));
-    Ok(index_url)
+    let header = IndexHeader {
+      title: if bins.arguments.title.is_empty() { None } else { Some(&bins.arguments.title[..]) },
+      description: if bins.arguments.description.is_empty() { None } else { Some(&bins.arguments.description[..]) }
+    };
+    let index = index::generate(format, &header, data, &files);
+    let index_file = PasteFile::new(String::from(format.file_name()), index);
+    let (url, raw_url, id, index_service) = match get_index_service(bins) {
+      Some(ref service) if service != self.service() => {
+        let result = try!(try!(bins.get_engine_by_service(service)).upload(bins, &vec![index_file]));
+        (result.url, result.raw_url, result.id, Some(result.service))
+      },
+      _ => {
+        let uploaded = try!(self.real_upload(bins, &index_file));
+        (uploaded.url, uploaded.raw_url, uploaded.id, None)
+      }
+    };
+    Ok(UploadResult {
+      service: self.service().to_owned(),
+      url: url,
+      raw_url: raw_url,
+      id: id,
+      delete_token: None,
+      delete_url: None,
+      index_service: index_service,
+      expiry: None,
+      files: files
+    })
+  }
+}
+
+impl UploadsBatches for BatchUpload {
+  fn service(&self) -> &'static str {
+    self.service
   }
 
-  fn generate_index(&self, data: &Vec<PasteFile>) -> String {
-    let header = format!("{} files", data.len());
-    let separator = Self::repeat_str("-", header.len());
-    let mut body = String::from("");
-    for (i, file) in data.iter().enumerate() {
-      let number = i + 1;
-      &body.push_str(&format!("{number}. {name}: <url{number}>\n", number = number, name = file.name));
+  fn real_upload(&self, bins: &Bins, data: &PasteFile) -> Result<UploadedFile> {
+    let request = Request::new(Method::Post, self.url.clone())
+      .headers(self.headers.clone())
+      .body(try!(self.body_producer.as_ref().produce_body(bins, data)));
+    if bins.arguments.dry_run {
+      println!("{}", request.describe());
+      return Ok(UploadedFile::dry_run(self.service, data));
+    }
+    let (res, s) = try!(request.send(bins.arguments.verbose));
+    if res.status.is_server_error() {
+      return Err(ErrorKind::ServerError(res.status.to_string()).into());
     }
-    header + "\n" + &separator + "\n\n" + &body
+    // 404 for pastie, which appears to have issues when redirecting?
+    if res.status != StatusCode::Ok && res.status != StatusCode::NotFound {
+      return Err(format!("paste could not be created ({}): {}", res.status, s.trim()).into());
+    }
+    self.url_producer.as_ref().produce_url(bins, data, res, s)
   }
+}
 
-  fn repeat_str(string: &str, count: usize) -> String {
-    repeat(string).take(count).collect()
+/// Gets the service to paste the index to if it isn't the one the files were pasted to: `--index-service`, or
+/// `defaults.index_service`.
+fn get_index_service(bins: &Bins) -> Option<String> {
+  if !bins.arguments.index_service.is_empty() {
+    return Some(bins.arguments.index_service.to_lowercase());
   }
+  bins.settings.defaults.index_service.as_ref().map(|s| s.to_lowercase())
 }
 
-impl UploadsBatches for BatchUpload {
-  fn real_upload(&self, bins: &Bins, data: &PasteFile) -> Result<String> {
-    let client = Client::new();
-    let mut res = try!(
-      client.post(&self.url)
-        .headers(self.headers.clone())
-        .body(&try!(self.body_producer.as_ref().produce_body(bins, data)))
-        .send()
-        .map_err(|e| e.to_string())
-    );
-    let mut s = String::from("");
-    try!(res.read_to_string(&mut s).map_err(|e| e.to_string()));
-    // 404 for pastie, which appears to have issues when redirecting?
-    if res.status != StatusCode::Ok && res.status != StatusCode::NotFound {
-      println!("{}", s);
-      return Err("paste could not be created".into());
+/// Gets the index format to use for `service`: `--index-format`, or the service's `index_format` setting.
+fn get_index_format(bins: &Bins, service: &str) -> Result<IndexFormat> {
+  if !bins.arguments.index_format.is_empty() {
+    return bins.arguments.index_format.parse();
+  }
+  Ok(bins.settings.index_formats.get(service).cloned().unwrap_or(IndexFormat::Text))
+}
+
+/// Downloads the files listed in `data` if it is the index of a batch upload, naming them with the paths they were
+/// uploaded with. Each file is downloaded from the service its url is on, since the index may be on another one, or
+/// with `engine` if the service isn't known.
+pub fn get_indexed_files(engine: &Engine, bins: &Bins, data: &str) -> Result<Option<Vec<PasteFile>>> {
+  let entries = match index::parse(data) {
+    Some(e) => e,
+    None => return Ok(None)
+  };
+  let mut files = Vec::new();
+  for entry in entries {
+    let url = try!(Url::parse(&entry.url).map_err(|e| format!("invalid url {} in index: {}", entry.url, e)));
+    let files_engine = match engines::get_service_for_url(&bins.settings, &url) {
+      Some(service) => Some(try!(bins.get_engine_by_service(service))),
+      None => None
+    };
+    let files_engine = files_engine.as_ref().map(|e| &**e).unwrap_or(engine);
+    for file in try!(files_engine.get(bins, &url)) {
+      if entry.sha1.as_ref().map(|s| s != &index::checksum(&file.data)).unwrap_or(false) {
+        let _ = writeln!(io::stderr(), "warning: {} does not match the checksum in the index", entry.name);
+      }
+      files.push(PasteFile::new(entry.name.clone(), file.data));
     }
-    self.url_producer.as_ref().produce_url(bins, res, s)
   }
+  Ok(Some(files))
 }
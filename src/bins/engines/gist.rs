@@ -1,12 +1,18 @@
 use bins::error::*;
 use bins::{Bins, PasteFile};
-use bins::engines::Engine;
-use hyper::client::Client;
+use bins::engines::{self, Engine, Privacy, UploadResult, UploadedFile};
+use bins::engines::batch;
+use bins::engines::index::IndexFormat;
+use bins::credentials::Netrc;
+use bins::login::DeviceFlow;
+use bins::http::Request;
+use bins::selection;
 use hyper::header::{ContentType, UserAgent, Authorization, Basic};
+use hyper::method::Method;
 use hyper::status::StatusCode;
 use std::collections::HashMap;
-use std::io::Read;
 use rustc_serialize::json::{self, Json};
+use url::Url;
 
 #[derive(RustcEncodable)]
 struct GistUpload {
@@ -25,15 +31,41 @@ impl GistUpload {
     }
   }
 
-  fn from(bins: &Bins, files: &Vec<PasteFile>) -> Self {
-    let mut gist = GistUpload::new(None, !bins.arguments.private);
+  fn from(bins: &Bins, files: &[PasteFile]) -> Self {
+    let description = if !bins.arguments.description.is_empty() {
+      Some(bins.arguments.description.clone())
+    } else if !bins.arguments.title.is_empty() {
+      Some(bins.arguments.title.clone())
+    } else {
+      None
+    };
+    let mut gist = GistUpload::new(description, bins.arguments.privacy == Privacy::Public);
     for file in files {
-      gist.files.insert(file.name.clone(), GistFile::from(file.data.clone()));
+      gist.files.insert(encode_name(&file.name), GistFile::from(file.data.clone()));
     }
     gist
   }
 }
 
+/// Gist file names can't contain `/`, so paths are uploaded with `%2F` in its place. `%` is written as `%25` so that
+/// names can always be decoded.
+fn encode_name(name: &str) -> String {
+  name.replace("%", "%25").replace("/", "%2F")
+}
+
+fn decode_name(name: &str) -> String {
+  name.replace("%2F", "/").replace("%25", "%")
+}
+
+/// Gets the anchor GitHub gives a file on a gist's page, like `file-main-rs` for `main.rs`.
+fn anchor_name(name: &str) -> String {
+  let slug: String = encode_name(name).to_lowercase()
+    .chars()
+    .map(|c| if c.is_alphanumeric() { c } else { '-' })
+    .collect();
+  format!("file-{}", slug)
+}
+
 #[derive(RustcEncodable)]
 struct GistFile {
   content: String
@@ -53,47 +85,206 @@ impl Gist {
   }
 }
 
+impl Gist {
+  /// Gets the username to send with the access token: `gist.username`, or the login for the API host in `~/.netrc`.
+  fn get_username(&self, bins: &Bins) -> Result<Option<String>> {
+    if let Some(ref username) = bins.settings.gist.username {
+      return Ok(Some(username.clone()));
+    }
+    let url = try!(Url::parse(&bins.settings.gist.url).map_err(|e| format!("invalid gist.url: {}", e)));
+    let host = some_or_err!(url.host_str(), "gist.url has no host".into());
+    Ok(try!(Netrc::find(host)).and_then(|m| m.login))
+  }
+
+  fn authenticate(&self, bins: &Bins, request: Request) -> Result<Request> {
+    if !bins.arguments.auth {
+      return Ok(request);
+    }
+    let token = match try!(bins.settings.gist.access_token.get()) {
+      Some(t) => t,
+      None => return Ok(request)
+    };
+    match try!(self.get_username(bins)) {
+      Some(username) => Ok(request.header(
+        Authorization(
+          Basic {
+            username: username,
+            password: Some(token)
+          }
+        )
+      )),
+      None => Ok(request.header(Authorization(format!("token {}", token))))
+    }
+  }
+}
+
 impl Engine for Gist {
-  fn upload(&self, bins: &Bins, data: &Vec<PasteFile>) -> Result<String> {
+  fn upload(&self, bins: &Bins, data: &[PasteFile]) -> Result<UploadResult> {
+    // only the selected lines are uploaded, at their own line numbers so that links to them match the file
+    let data = &data.iter().map(selection::place_at_original_lines).collect::<Vec<_>>();
     let upload = GistUpload::from(bins, data);
     let j = try!(json::encode(&upload).map_err(|e| e.to_string()));
-    let client = Client::new();
-    let mut res = try!({
-      let mut builder = client
-        .post("https://api.github.com/gists")
-        .body(&j)
+    let request = try!(self.authenticate(bins,
+      Request::new(Method::Post, format!("{}/gists", bins.settings.gist.url.trim_right_matches('/')))
+        .body(j)
         .header(ContentType::json())
-        .header(UserAgent(String::from("bins")));
-      if bins.arguments.auth {
-        if let Some(username) = bins.config.lookup_str("gist.username") {
-          if let Some(token) = bins.config.lookup_str("gist.access_token") {
-            if !username.is_empty() && !token.is_empty() {
-              builder = builder.header(
-                Authorization(
-                  Basic {
-                    username: username.to_owned(),
-                    password: Some(token.to_owned())
-                  }
-                )
-              );
-            }
-          }
-        }
-      }
-      builder
-        .send()
-        .map_err(|e| e.to_string())
-    });
-    let mut s = String::from("");
-    try!(res.read_to_string(&mut s).map_err(|e| e.to_string()));
+        .header(UserAgent(String::from("bins")))
+    ));
+    if bins.arguments.dry_run {
+      println!("{}", request.describe());
+      return Ok(UploadResult {
+        service: String::from("gist"),
+        url: String::from("dry-run://gist"),
+        raw_url: None,
+        id: None,
+        delete_token: None,
+        delete_url: None,
+        index_service: None,
+        expiry: None,
+        files: data.iter().map(|f| UploadedFile::dry_run("gist", f)).collect()
+      });
+    }
+    let (res, s) = try!(request.send(bins.arguments.verbose));
+    if res.status.is_server_error() {
+      return Err(ErrorKind::ServerError(res.status.to_string()).into());
+    }
     if res.status != StatusCode::Created {
-      println!("{}", s);
-      return Err("paste could not be created".into());
+      return Err(format!("paste could not be created ({}): {}", res.status, s.trim()).into());
     }
     let raw_gist = try!(Json::from_str(&s).map_err(|e| e.to_string()));
     let gist = some_or_err!(raw_gist.as_object(), "response was not a json object".into());
     let html_url = some_or_err!(gist.get("html_url"), "no html_url_key".into());
-    let url = some_or_err!(html_url.as_string(), "html_url was not a string".into());
-    Ok(url.to_owned())
+    let html_url = some_or_err!(html_url.as_string(), "html_url was not a string".into());
+    let id = gist.get("id").and_then(|i| i.as_string()).map(|i| i.to_owned());
+    let raw_files = gist.get("files").and_then(|f| f.as_object());
+    let mut files = Vec::new();
+    for file in data {
+      let raw_url = raw_files
+        .and_then(|f| f.get(&encode_name(&file.name)))
+        .and_then(|f| f.as_object())
+        .and_then(|f| f.get("raw_url"))
+        .and_then(|u| u.as_string())
+        .map(|u| u.to_owned());
+      let url = match file.selection {
+        Some(ref s) if s.start == s.end => format!("{}#{}-L{}", html_url, anchor_name(&file.name), s.start),
+        Some(ref s) => format!("{}#{}-L{}-L{}", html_url, anchor_name(&file.name), s.start, s.end),
+        None => format!("{}#{}", html_url, anchor_name(&file.name))
+      };
+      files.push(UploadedFile {
+        name: file.name.clone(),
+        url: url,
+        raw_url: raw_url,
+        id: None,
+        size: file.data.len()
+      });
+    }
+    // links to the selected lines if part of a file was pasted
+    let url = match data.iter().position(|f| f.selection.is_some()) {
+      Some(i) => files[i].url.clone(),
+      None => html_url.to_owned()
+    };
+    Ok(UploadResult {
+      service: String::from("gist"),
+      url: url,
+      raw_url: if files.len() == 1 { files[0].raw_url.clone() } else { None },
+      id: id,
+      delete_token: None,
+      delete_url: None,
+      index_service: None,
+      expiry: None,
+      files: files
+    })
+  }
+
+  fn privacy_levels(&self) -> &'static [Privacy] {
+    // secret gists are left out of listings, but anyone with the url can see them
+    &[Privacy::Public, Privacy::Unlisted]
+  }
+
+  fn get(&self, bins: &Bins, url: &Url) -> Result<Vec<PasteFile>> {
+    let id = try!(engines::get_paste_id(url));
+    let request = try!(self.authenticate(bins,
+      Request::new(Method::Get, format!("{}/gists/{}", bins.settings.gist.url.trim_right_matches('/'), id))
+        .header(UserAgent(String::from("bins")))
+    ));
+    let (res, s) = try!(request.send(bins.arguments.verbose));
+    if res.status != StatusCode::Ok {
+      return Err(format!("paste could not be downloaded ({})", res.status).into());
+    }
+    let raw_gist = try!(Json::from_str(&s).map_err(|e| e.to_string()));
+    let gist = some_or_err!(raw_gist.as_object(), "response was not a json object".into());
+    let raw_files = some_or_err!(gist.get("files"), "no files key".into());
+    let files = some_or_err!(raw_files.as_object(), "files was not a json object".into());
+    let mut pastes = Vec::new();
+    for (name, raw_file) in files {
+      let file = some_or_err!(raw_file.as_object(), "file was not a json object".into());
+      let raw_content = some_or_err!(file.get("content"), "no content key".into());
+      let content = some_or_err!(raw_content.as_string(), "content was not a string".into());
+      pastes.push(PasteFile::new(decode_name(name), content.to_owned()));
+    }
+    // the index of a multi-file paste on another service, with --index-service
+    if pastes.len() == 1 && IndexFormat::is_index_name(&pastes[0].name) {
+      if let Some(files) = try!(batch::get_indexed_files(self, bins, &pastes[0].data)) {
+        return Ok(files);
+      }
+    }
+    Ok(pastes)
+  }
+
+  fn delete(&self, bins: &Bins, url: &Url) -> Result<()> {
+    let id = try!(engines::get_paste_id(url));
+    let request = try!(self.authenticate(bins,
+      Request::new(Method::Delete, format!("{}/gists/{}", bins.settings.gist.url.trim_right_matches('/'), id))
+        .header(UserAgent(String::from("bins")))
+    ));
+    let (res, _) = try!(request.send(bins.arguments.verbose));
+    if res.status != StatusCode::NoContent {
+      return Err(format!("paste could not be deleted ({})", res.status).into());
+    }
+    Ok(())
+  }
+
+  /// Logs in with GitHub's device flow and saves the token for the active profile.
+  fn login(&self, bins: &Bins) -> Result<()> {
+    let settings = &bins.settings.gist;
+    let client_id = some_or_err!(
+      settings.client_id.as_ref(),
+      "set gist.client_id to the client id of a GitHub OAuth app with the device flow enabled".into()
+    );
+    let flow = DeviceFlow {
+      url: &settings.oauth_url,
+      client_id: client_id,
+      scope: "gist",
+      verbose: bins.arguments.verbose
+    };
+    let code = try!(flow.request_code());
+    println!("open {} and enter the code {}", code.verification_uri, code.user_code);
+    let token = try!(flow.poll(&code));
+    if !token.scopes.iter().any(|s| s == "gist") {
+      return Err(format!("the token was granted without the gist scope (got \"{}\")", token.scopes.join(",")).into());
+    }
+    let path = try!(settings.access_token.store(&token.token));
+    println!("logged in, the token was saved to {}", path.to_string_lossy());
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{anchor_name, decode_name, encode_name};
+
+  #[test]
+  fn names_with_slashes_and_percents_round_trip() {
+    assert_eq!(encode_name("src/main.rs"), "src%2Fmain.rs");
+    assert_eq!(encode_name("100%/a%2F"), "100%25%2Fa%252F");
+    for name in &["main.rs", "src/bins/mod.rs", "100%/a%2F", "%25%2F"] {
+      assert_eq!(decode_name(&encode_name(name)), *name);
+    }
+  }
+
+  #[test]
+  fn anchors_name_the_encoded_file() {
+    assert_eq!(anchor_name("main.rs"), "file-main-rs");
+    assert_eq!(anchor_name("src/Main.rs"), "file-src-2fmain-rs");
   }
 }
@@ -1,9 +1,10 @@
 use bins::error::*;
 use bins::{Bins, PasteFile};
-use bins::engines::Engine;
+use bins::engines::{self, Engine, Privacy, UploadResult, UploadedFile};
 use hyper::client::Response;
+use url::Url;
 use rustc_serialize::json::Json;
-use bins::engines::batch::{BatchUpload, UploadsBatches, ProducesUrl, ProducesBody};
+use bins::engines::batch::{self, BatchUpload, UploadsBatches, ProducesUrl, ProducesBody};
 use hyper::header::Headers;
 
 pub struct Hastebin {
@@ -11,30 +12,39 @@ pub struct Hastebin {
 }
 
 impl Hastebin {
-  pub fn new() -> Self {
+  /// Creates a Hastebin engine for the server at `url`, like `http://hastebin.com`.
+  pub fn new(url: &str) -> Self {
     Hastebin {
       batch_upload: BatchUpload {
-        url: String::from("http://hastebin.com/documents"),
+        service: "hastebin",
+        url: format!("{}/documents", url.trim_right_matches('/')),
         headers: Headers::new(),
-        url_producer: Box::new(HastebinUrlProducer { }),
+        url_producer: Box::new(HastebinUrlProducer { url: url.trim_right_matches('/').to_owned() }),
         body_producer: Box::new(HastebinBodyProducer { })
       }
     }
   }
 }
 
-struct HastebinUrlProducer { }
+struct HastebinUrlProducer {
+  /// The configured server, which pastes and their raw contents are served from.
+  url: String
+}
 
 impl ProducesUrl for HastebinUrlProducer {
   #[allow(unused_variables)]
-  fn produce_url(&self, bins: &Bins, res: Response, data: String) -> Result<String> {
+  fn produce_url(&self, bins: &Bins, file: &PasteFile, res: Response, data: String) -> Result<UploadedFile> {
     let raw_response = try!(Json::from_str(&data).map_err(|e| e.to_string()));
     let response = some_or_err!(raw_response.as_object(), "response was not a json object".into());
     let raw_key = some_or_err!(response.get("key"), "no key".into());
     let key = some_or_err!(raw_key.as_string(), "key was not a string".into());
-    let scheme = res.url.scheme();
-    let host = some_or_err!(res.url.host_str(), "no host string".into());
-    Ok(format!("{}://{}/{}", scheme, host, key))
+    Ok(UploadedFile {
+      name: file.name.clone(),
+      url: format!("{}/{}", self.url, key),
+      raw_url: Some(format!("{}/raw/{}", self.url, key)),
+      id: Some(key.to_owned()),
+      size: file.data.len()
+    })
   }
 }
 
@@ -48,7 +58,22 @@ impl ProducesBody for HastebinBodyProducer {
 }
 
 impl Engine for Hastebin {
-  fn upload(&self, bins: &Bins, data: &Vec<PasteFile>) -> Result<String> {
+  fn upload(&self, bins: &Bins, data: &[PasteFile]) -> Result<UploadResult> {
     self.batch_upload.upload(bins, data)
   }
+
+  fn privacy_levels(&self) -> &'static [Privacy] {
+    // anyone can look up a key, so there is nothing like an unlisted paste
+    &[Privacy::Public]
+  }
+
+  fn get(&self, bins: &Bins, url: &Url) -> Result<Vec<PasteFile>> {
+    let key = try!(engines::get_paste_id(url));
+    let host = some_or_err!(url.host_str(), "no host string".into());
+    let data = try!(engines::download(bins, &format!("{}://{}/raw/{}", url.scheme(), host, key)));
+    if let Some(files) = try!(batch::get_indexed_files(self, bins, &data)) {
+      return Ok(files);
+    }
+    Ok(vec![PasteFile::new(key, data)])
+  }
 }
@@ -0,0 +1,320 @@
+use bins::error::*;
+use bins::PasteFile;
+use bins::engines::UploadedFile;
+use rustc_serialize::json::{self, Json};
+use sha1::Sha1;
+use std::collections::BTreeMap;
+use std::iter::repeat;
+use std::str::FromStr;
+
+/// The layouts of the index paste listing the files of a batch upload.
+#[derive(Clone, Copy, PartialEq)]
+pub enum IndexFormat {
+  Text,
+  Markdown,
+  Html,
+  /// A manifest that `bins get` reads to download the files and check their checksums.
+  Json
+}
+
+pub const INDEX_FORMATS: &'static [&'static str] = &["text", "markdown", "html", "json"];
+
+impl FromStr for IndexFormat {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    match s {
+      "text" => Ok(IndexFormat::Text),
+      "markdown" => Ok(IndexFormat::Markdown),
+      "html" => Ok(IndexFormat::Html),
+      "json" => Ok(IndexFormat::Json),
+      _ => Err(format!("unknown index format \"{}\" (use {})", s, INDEX_FORMATS.join(", ")).into())
+    }
+  }
+}
+
+impl IndexFormat {
+  /// Checks if `name` is the name an index paste is given in any format.
+  pub fn is_index_name(name: &str) -> bool {
+    [IndexFormat::Text, IndexFormat::Markdown, IndexFormat::Html, IndexFormat::Json]
+      .iter()
+      .any(|f| f.file_name() == name)
+  }
+
+  /// Gets the name of the index paste, whose extension lets services highlight it.
+  pub fn file_name(&self) -> &'static str {
+    match *self {
+      IndexFormat::Text => "index",
+      IndexFormat::Markdown => "index.md",
+      IndexFormat::Html => "index.html",
+      IndexFormat::Json => "index.json"
+    }
+  }
+}
+
+/// A title and description to put at the top of an index.
+pub struct IndexHeader<'a> {
+  pub title: Option<&'a str>,
+  pub description: Option<&'a str>
+}
+
+/// A file listed in an index read back from a paste.
+pub struct IndexEntry {
+  pub name: String,
+  pub url: String,
+  pub sha1: Option<String>
+}
+
+pub fn checksum(data: &str) -> String {
+  let mut sha1 = Sha1::new();
+  sha1.update(data.as_bytes());
+  sha1.hexdigest()
+}
+
+/// Writes an index of `files`, which were pasted as `uploads`.
+pub fn generate(format: IndexFormat, header: &IndexHeader, files: &[PasteFile], uploads: &[UploadedFile]) -> String {
+  let entries = files.iter().zip(uploads.iter()).collect::<Vec<_>>();
+  match format {
+    IndexFormat::Text => generate_text(header, &entries),
+    IndexFormat::Markdown => generate_markdown(header, &entries),
+    IndexFormat::Html => generate_html(header, &entries),
+    IndexFormat::Json => generate_json(header, &entries)
+  }
+}
+
+fn generate_text(header: &IndexHeader, entries: &[(&PasteFile, &UploadedFile)]) -> String {
+  let mut heading = format!("{} files", entries.len());
+  if let Some(title) = header.title {
+    heading.push_str(&format!(": {}", title));
+  }
+  let separator: String = repeat("-").take(heading.chars().count()).collect();
+  let mut text = heading + "\n" + &separator + "\n\n";
+  if let Some(description) = header.description {
+    text.push_str(&format!("{}\n\n", description));
+  }
+  for (i, &(file, upload)) in entries.iter().enumerate() {
+    text.push_str(&format!("{}. {}: {} ({} bytes, sha1 {})\n", i + 1, file.name, upload.url, upload.size,
+                           checksum(&file.data)));
+  }
+  text
+}
+
+fn generate_markdown(header: &IndexHeader, entries: &[(&PasteFile, &UploadedFile)]) -> String {
+  let mut text = format!("# {}\n\n", header.title.unwrap_or("Files"));
+  if let Some(description) = header.description {
+    text.push_str(&format!("{}\n\n", description));
+  }
+  text.push_str("| File | Size | SHA-1 |\n|------|------|-------|\n");
+  for &(file, upload) in entries {
+    text.push_str(&format!("| [{}]({}) | {} bytes | `{}` |\n", file.name.replace("|", "\\|"), upload.url, upload.size,
+                           checksum(&file.data)));
+  }
+  text
+}
+
+fn escape_html(text: &str) -> String {
+  text.replace("&", "&amp;").replace("<", "&lt;").replace(">", "&gt;").replace("\"", "&quot;")
+}
+
+fn generate_html(header: &IndexHeader, entries: &[(&PasteFile, &UploadedFile)]) -> String {
+  let title = escape_html(header.title.unwrap_or("Files"));
+  let mut html = format!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{0}</title>\n</head>\n<body>\n\
+                          <h1>{0}</h1>\n", title);
+  if let Some(description) = header.description {
+    html.push_str(&format!("<p>{}</p>\n", escape_html(description)));
+  }
+  html.push_str("<table>\n<tr><th>File</th><th>Size</th><th>SHA-1</th></tr>\n");
+  for &(file, upload) in entries {
+    html.push_str(&format!("<tr><td><a href=\"{}\">{}</a></td><td>{} bytes</td><td><code>{}</code></td></tr>\n",
+                           escape_html(&upload.url), escape_html(&file.name), upload.size, checksum(&file.data)));
+  }
+  html.push_str("</table>\n</body>\n</html>\n");
+  html
+}
+
+fn generate_json(header: &IndexHeader, entries: &[(&PasteFile, &UploadedFile)]) -> String {
+  let mut manifest = BTreeMap::new();
+  manifest.insert(String::from("bins_index"), Json::U64(1));
+  if let Some(title) = header.title {
+    manifest.insert(String::from("title"), Json::String(title.to_owned()));
+  }
+  if let Some(description) = header.description {
+    manifest.insert(String::from("description"), Json::String(description.to_owned()));
+  }
+  let files = entries.iter()
+    .map(|&(file, upload)| {
+      let mut entry = BTreeMap::new();
+      entry.insert(String::from("name"), Json::String(file.name.clone()));
+      entry.insert(String::from("url"), Json::String(upload.url.clone()));
+      if let Some(ref raw_url) = upload.raw_url {
+        entry.insert(String::from("raw_url"), Json::String(raw_url.clone()));
+      }
+      entry.insert(String::from("size"), Json::U64(upload.size as u64));
+      entry.insert(String::from("sha1"), Json::String(checksum(&file.data)));
+      Json::Object(entry)
+    })
+    .collect();
+  manifest.insert(String::from("files"), Json::Array(files));
+  format!("{}\n", json::as_pretty_json(&Json::Object(manifest)))
+}
+
+/// Reads an index in the text or JSON format, getting each file in it. Returns `None` if `text` isn't an index.
+pub fn parse(text: &str) -> Option<Vec<IndexEntry>> {
+  if text.trim_left().starts_with('{') {
+    parse_json(text)
+  } else {
+    parse_text(text)
+  }
+}
+
+fn parse_json(text: &str) -> Option<Vec<IndexEntry>> {
+  let manifest = match Json::from_str(text) {
+    Ok(m) => m,
+    Err(_) => return None
+  };
+  if manifest.find("bins_index").is_none() {
+    return None;
+  }
+  let files = match manifest.find("files").and_then(|f| f.as_array()) {
+    Some(f) => f,
+    None => return None
+  };
+  let mut entries = Vec::new();
+  for file in files {
+    let name = file.find("name").and_then(|n| n.as_string());
+    let url = file.find("url").and_then(|u| u.as_string());
+    match (name, url) {
+      (Some(name), Some(url)) => entries.push(IndexEntry {
+        name: name.to_owned(),
+        url: url.to_owned(),
+        sha1: file.find("sha1").and_then(|s| s.as_string()).map(|s| s.to_owned())
+      }),
+      _ => return None
+    }
+  }
+  Some(entries)
+}
+
+fn parse_text(text: &str) -> Option<Vec<IndexEntry>> {
+  let mut lines = text.lines();
+  let heading = match lines.next() {
+    Some(h) => h.split(": ").next().unwrap_or(h),
+    None => return None
+  };
+  let count = match heading.trim_right_matches(" files").parse::<usize>() {
+    Ok(c) => c,
+    Err(_) => return None
+  };
+  if !lines.next().map(|s| !s.is_empty() && s.chars().all(|c| c == '-')).unwrap_or(false) {
+    return None;
+  }
+  let mut entries = Vec::new();
+  // anything before the first entry is the description
+  for line in lines.skip_while(|l| !l.starts_with("1. ")).filter(|l| !l.is_empty()) {
+    let prefix = format!("{}. ", entries.len() + 1);
+    if !line.starts_with(&prefix) {
+      return None;
+    }
+    let mut entry = &line[prefix.len()..];
+    let mut sha1 = None;
+    // older indexes don't have sizes and checksums
+    if entry.ends_with(')') {
+      if let Some(start) = entry.rfind(" (") {
+        sha1 = entry[start + 2..entry.len() - 1].split("sha1 ").nth(1).map(|s| s.to_owned());
+        entry = &entry[..start];
+      }
+    }
+    let split = match entry.rfind(": ") {
+      Some(s) => s,
+      None => return None
+    };
+    entries.push(IndexEntry {
+      name: entry[..split].to_owned(),
+      url: entry[split + 2..].to_owned(),
+      sha1: sha1
+    });
+  }
+  if entries.len() != count {
+    return None;
+  }
+  Some(entries)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{checksum, generate, parse, parse_json, parse_text, IndexFormat, IndexHeader};
+  use bins::PasteFile;
+  use bins::engines::UploadedFile;
+
+  #[test]
+  fn only_the_names_of_index_pastes_are_index_names() {
+    assert!(IndexFormat::is_index_name("index"));
+    assert!(IndexFormat::is_index_name("index.json"));
+    assert!(!IndexFormat::is_index_name("index.rs"));
+    assert!(!IndexFormat::is_index_name("indexes.md"));
+  }
+
+  #[test]
+  fn text_indexes_keep_colons_in_names() {
+    let text = "2 files\n-------\n\n1. a: b.txt: http://x/1 (4 bytes, sha1 abc)\n2. c.rs: http://x/2\n";
+    let entries = parse_text(text).unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].name, "a: b.txt");
+    assert_eq!(entries[0].url, "http://x/1");
+    assert_eq!(entries[0].sha1, Some(String::from("abc")));
+    assert_eq!(entries[1].name, "c.rs");
+    assert_eq!(entries[1].url, "http://x/2");
+    assert_eq!(entries[1].sha1, None);
+  }
+
+  #[test]
+  fn text_indexes_skip_their_description() {
+    let text = "1 files: notes\n--------------\n\nsome notes\n\n1. a.txt: http://x/1\n";
+    let entries = parse_text(text).unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].name, "a.txt");
+  }
+
+  #[test]
+  fn other_text_is_not_an_index() {
+    assert!(parse_text("hello world\n").is_none());
+    // the heading counts two files but only one is listed
+    assert!(parse_text("2 files\n-------\n\n1. a: http://x/1\n").is_none());
+    assert!(parse_text("1 files\n\n1. a: http://x/1\n").is_none());
+  }
+
+  #[test]
+  fn json_indexes_need_the_marker_and_a_url_for_each_file() {
+    let text = r#"{"bins_index": 1, "files": [{"name": "a: b", "url": "http://x/1", "sha1": "abc"}]}"#;
+    let entries = parse_json(text).unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].name, "a: b");
+    assert_eq!(entries[0].url, "http://x/1");
+    assert_eq!(entries[0].sha1, Some(String::from("abc")));
+    assert!(parse_json(r#"{"files": []}"#).is_none());
+    assert!(parse_json(r#"{"bins_index": 1, "files": [{"name": "a"}]}"#).is_none());
+  }
+
+  #[test]
+  fn generated_text_and_json_indexes_read_back() {
+    let files = vec![
+      PasteFile::new(String::from("src/main.rs"), String::from("fn main() {}\n")),
+      PasteFile::new(String::from("notes: todo.txt"), String::from("nothing\n"))
+    ];
+    let uploads = vec![
+      UploadedFile { name: files[0].name.clone(), url: String::from("http://x/1"), raw_url: None, id: None, size: 13 },
+      UploadedFile { name: files[1].name.clone(), url: String::from("http://x/2"), raw_url: None, id: None, size: 8 }
+    ];
+    let header = IndexHeader { title: Some("a title: with a colon"), description: Some("what these are") };
+    for format in &[IndexFormat::Text, IndexFormat::Json] {
+      let entries = parse(&generate(*format, &header, &files, &uploads)).unwrap();
+      assert_eq!(entries.len(), 2);
+      assert_eq!(entries[0].name, "src/main.rs");
+      assert_eq!(entries[0].url, "http://x/1");
+      assert_eq!(entries[0].sha1, Some(checksum("fn main() {}\n")));
+      assert_eq!(entries[1].name, "notes: todo.txt");
+      assert_eq!(entries[1].url, "http://x/2");
+      assert_eq!(entries[1].sha1, Some(checksum("nothing\n")));
+    }
+  }
+}
@@ -2,12 +2,206 @@ pub mod gist;
 pub mod hastebin;
 pub mod pastie;
 pub mod pastebin;
+pub mod index;
 mod batch;
 
 use bins::error::*;
 use bins::PasteFile;
 use bins::Bins;
+use bins::settings::Settings;
+use bins::engines::gist::Gist;
+use bins::engines::hastebin::Hastebin;
+use bins::engines::pastie::Pastie;
+use bins::engines::pastebin::Pastebin;
+use bins::http::Request;
+use hyper::header::UserAgent;
+use hyper::method::Method;
+use hyper::status::StatusCode;
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+use url::Url;
+
+pub const SERVICES: &'static [&'static str] = &["gist", "hastebin", "pastebin", "pastie"];
+
+/// How hidden a paste is, from least to most.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Privacy {
+  /// Listed by the service, so anyone can find it.
+  Public,
+  /// Not listed, but anyone with the url can see it.
+  Unlisted,
+  /// Only the account that made it can see it.
+  Private,
+  /// Encrypted before it is pasted, so not even the service can read it.
+  Encrypted
+}
+
+pub const PRIVACY_LEVELS: &'static [&'static str] = &["public", "unlisted", "private", "encrypted"];
+
+impl FromStr for Privacy {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    match s {
+      "public" => Ok(Privacy::Public),
+      "unlisted" => Ok(Privacy::Unlisted),
+      "private" => Ok(Privacy::Private),
+      "encrypted" => Ok(Privacy::Encrypted),
+      _ => Err(format!("unknown privacy level \"{}\" (use {})", s, PRIVACY_LEVELS.join(", ")).into())
+    }
+  }
+}
+
+impl fmt::Display for Privacy {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str(match *self {
+      Privacy::Public => "public",
+      Privacy::Unlisted => "unlisted",
+      Privacy::Private => "private",
+      Privacy::Encrypted => "encrypted"
+    })
+  }
+}
+
+/// A paste made for one file.
+#[derive(Clone, RustcEncodable)]
+pub struct UploadedFile {
+  pub name: String,
+  pub url: String,
+  /// The url of the file's contents without any page around them.
+  pub raw_url: Option<String>,
+  pub id: Option<String>,
+  /// The size of the file in bytes.
+  pub size: usize
+}
+
+impl UploadedFile {
+  /// Describes a file that `--dry-run` didn't paste, with a placeholder url saying where it would have gone.
+  pub fn dry_run(service: &str, file: &PasteFile) -> Self {
+    UploadedFile {
+      name: file.name.clone(),
+      url: format!("dry-run://{}/{}", service, file.name),
+      raw_url: None,
+      id: None,
+      size: file.data.len()
+    }
+  }
+}
+
+/// What an upload created: one paste holding every file, or for services that paste files one at a time, a paste for
+/// each file and an index paste listing them.
+#[derive(RustcEncodable)]
+pub struct UploadResult {
+  pub service: String,
+  /// The url to share: the paste, or the index paste if there is one. It is empty if the files were pasted on their own
+  /// with `--no-index`.
+  pub url: String,
+  pub raw_url: Option<String>,
+  pub id: Option<String>,
+  /// A token needed to delete the paste, for services that give one.
+  pub delete_token: Option<String>,
+  /// A url that deletes the paste when opened, for services that give one.
+  pub delete_url: Option<String>,
+  /// The service the index paste is on, if it was pasted to another one with `--index-service`.
+  pub index_service: Option<String>,
+  /// How long the paste lasts, if it expires.
+  pub expiry: Option<String>,
+  pub files: Vec<UploadedFile>
+}
 
 pub trait Engine {
-  fn upload(&self, bins: &Bins, data: &Vec<PasteFile>) -> Result<String>;
+  fn upload(&self, bins: &Bins, data: &[PasteFile]) -> Result<UploadResult>;
+
+  /// Gets the privacy levels this service can make pastes with. Engines make pastes as hidden as they can when
+  /// anything above public is asked for, so `check_privacy` has to pass before uploading.
+  fn privacy_levels(&self) -> &'static [Privacy];
+
+  #[allow(unused_variables)]
+  fn get(&self, bins: &Bins, url: &Url) -> Result<Vec<PasteFile>> {
+    Err("downloading pastes is not supported by this service".into())
+  }
+
+  #[allow(unused_variables)]
+  fn delete(&self, bins: &Bins, url: &Url) -> Result<()> {
+    Err("deleting pastes is not supported by this service".into())
+  }
+
+  #[allow(unused_variables)]
+  fn login(&self, bins: &Bins) -> Result<()> {
+    Err("logging in is not supported by this service".into())
+  }
+}
+
+pub fn get_engine_by_name(settings: &Settings, name: &str) -> Option<Box<Engine>> {
+  match name.to_lowercase().as_ref() {
+    "gist" => Some(Box::new(Gist::new())),
+    "hastebin" => Some(Box::new(Hastebin::new(&settings.hastebin.url))),
+    "pastie" => Some(Box::new(Pastie::new())),
+    "pastebin" => Some(Box::new(Pastebin::new())),
+    _ => None
+  }
+}
+
+/// Checks that `engine`, the engine for `service`, can make pastes with the privacy asked for. With
+/// `--allow-weaker-privacy`, pastes that would be less hidden only give a warning.
+pub fn check_privacy(bins: &Bins, service: &str, engine: &Engine) -> Result<()> {
+  let wanted = bins.arguments.privacy;
+  let levels = engine.privacy_levels();
+  // a level that wasn't asked for, like the built-in one, is made as well as the service can
+  if levels.contains(&wanted) || !bins.arguments.require_privacy {
+    return Ok(());
+  }
+  let names = levels.iter().map(|l| l.to_string()).collect::<Vec<_>>().join(", ");
+  if !bins.arguments.allow_weaker_privacy {
+    return Err(format!("{} can't make {} pastes, only {} ones (use --allow-weaker-privacy to paste anyway)", service,
+                       wanted, names).into());
+  }
+  let _ = writeln!(io::stderr(), "{} can't make {} pastes, making a {} one instead", service, wanted,
+                   get_privacy(bins, engine));
+  Ok(())
+}
+
+/// Gets the privacy `engine` will make pastes with: the level asked for, or the most hidden one below it that the
+/// service has.
+pub fn get_privacy(bins: &Bins, engine: &Engine) -> Privacy {
+  let wanted = bins.arguments.privacy;
+  // every engine can make public pastes
+  engine.privacy_levels().iter().cloned().filter(|&l| l <= wanted).max().unwrap_or(Privacy::Public)
+}
+
+/// Gets the name of the service that hosts the paste at `url`, including the hosts configured in `gist.url` and
+/// `hastebin.url`.
+pub fn get_service_for_url(settings: &Settings, url: &Url) -> Option<&'static str> {
+  let host = url.host_str();
+  if host.is_some() && host == Url::parse(&settings.hastebin.url).ok().as_ref().and_then(|u| u.host_str()) {
+    return Some("hastebin");
+  }
+  if host.is_some() && host == Url::parse(&settings.gist.url).ok().as_ref().and_then(|u| u.host_str()) {
+    return Some("gist");
+  }
+  match host {
+    Some("gist.github.com") => Some("gist"),
+    Some("hastebin.com") => Some("hastebin"),
+    Some("pastebin.com") => Some("pastebin"),
+    Some("pastie.org") => Some("pastie"),
+    _ => None
+  }
+}
+
+/// Gets the last non-empty path segment of a paste url, which all supported services use as the paste's id.
+pub fn get_paste_id(url: &Url) -> Result<String> {
+  let segments = some_or_err!(url.path_segments(), format!("{} is not a paste url", url).into());
+  let id = some_or_err!(segments.filter(|s| !s.is_empty()).last(), format!("{} has no paste id", url).into());
+  Ok(id.to_owned())
+}
+
+/// Downloads the body of `url` as a string, failing on any status other than 200.
+pub fn download(bins: &Bins, url: &str) -> Result<String> {
+  let request = Request::new(Method::Get, url.to_owned()).header(UserAgent(String::from("bins")));
+  let (res, s) = try!(request.send(bins.arguments.verbose));
+  if res.status != StatusCode::Ok {
+    return Err(format!("paste could not be downloaded ({})", res.status).into());
+  }
+  Ok(s)
 }
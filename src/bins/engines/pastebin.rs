@@ -1,10 +1,10 @@
 use bins::error::*;
 use bins::{Bins, PasteFile};
-use bins::engines::Engine;
+use bins::engines::{self, Engine, Privacy, UploadResult, UploadedFile};
 use hyper::client::Response;
-use bins::engines::batch::{BatchUpload, UploadsBatches, ProducesUrl, ProducesBody};
+use bins::engines::batch::{self, BatchUpload, UploadsBatches, ProducesUrl, ProducesBody};
 use hyper::header::{Headers, ContentType};
-use url::form_urlencoded;
+use url::{form_urlencoded, Url};
 
 pub struct Pastebin {
   batch_upload: BatchUpload
@@ -16,6 +16,7 @@ impl Pastebin {
     &headers.set(ContentType::form_url_encoded());
     Pastebin {
       batch_upload: BatchUpload {
+        service: "pastebin",
         url: String::from("http://pastebin.com/api/api_post.php"),
         headers: headers,
         url_producer: Box::new(PastebinUrlProducer { }),
@@ -29,24 +30,49 @@ struct PastebinUrlProducer { }
 
 impl ProducesUrl for PastebinUrlProducer {
   #[allow(unused_variables)]
-  fn produce_url(&self, bins: &Bins, res: Response, data: String) -> Result<String> {
-    Ok(data)
+  fn produce_url(&self, bins: &Bins, file: &PasteFile, res: Response, data: String) -> Result<UploadedFile> {
+    let url = try!(Url::parse(data.trim()).map_err(|_| format!("paste could not be created: {}", data.trim())));
+    let id = try!(engines::get_paste_id(&url));
+    Ok(UploadedFile {
+      name: file.name.clone(),
+      url: url.as_str().to_owned(),
+      raw_url: Some(format!("http://pastebin.com/raw/{}", id)),
+      id: Some(id),
+      size: file.data.len()
+    })
   }
 }
 
 struct PastebinBodyProducer { }
 
+impl PastebinBodyProducer {
+  fn expire_date(bins: &Bins) -> &'static str {
+    match bins.settings.defaults.expiry.as_ref().map(|e| e.as_ref()) {
+      Some("10m") => "10M",
+      Some("1h") => "1H",
+      Some("1d") => "1D",
+      Some("1w") => "1W",
+      Some("2w") => "2W",
+      Some("1mo") => "1M",
+      Some("6mo") => "6M",
+      Some("1y") => "1Y",
+      _ => "N"
+    }
+  }
+}
+
 impl ProducesBody for PastebinBodyProducer {
   fn produce_body(&self, bins: &Bins, data: &PasteFile) -> Result<String> {
-    let api_key = some_or_err!(bins.config.lookup_str("pastebin.api_key"), "no pastebin.api_key defined in configuration file".into());
-    if api_key.is_empty() {
-      return Err("pastebin.api_key was empty".into());
-    }
+    let api_key = some_or_err!(
+      try!(bins.settings.pastebin.api_key.get()),
+      "no pastebin.api_key, pastebin.api_key_command, pastebin.api_key_file or ~/.netrc entry for pastebin.com".into()
+    );
     Ok(
       form_urlencoded::Serializer::new(String::new())
         .append_pair("api_option", "paste")
         .append_pair("api_dev_key", &api_key)
-        .append_pair("api_paste_private", if bins.arguments.private { "1" } else { "0" })
+        .append_pair("api_paste_private", if bins.arguments.privacy > Privacy::Public { "1" } else { "0" })
+        .append_pair("api_paste_expire_date", PastebinBodyProducer::expire_date(bins))
         .append_pair("api_paste_code", &data.data)
         .append_pair("api_paste_name", &data.name)
         .finish()
@@ -55,7 +81,23 @@ impl ProducesBody for PastebinBodyProducer {
 }
 
 impl Engine for Pastebin {
-  fn upload(&self, bins: &Bins, data: &Vec<PasteFile>) -> Result<String> {
-    self.batch_upload.upload(bins, data)
+  fn upload(&self, bins: &Bins, data: &[PasteFile]) -> Result<UploadResult> {
+    let mut result = try!(self.batch_upload.upload(bins, data));
+    result.expiry = bins.settings.defaults.expiry.clone().and_then(|e| if e == "never" { None } else { Some(e) });
+    Ok(result)
+  }
+
+  fn privacy_levels(&self) -> &'static [Privacy] {
+    // private pastes need a user key, which isn't supported
+    &[Privacy::Public, Privacy::Unlisted]
+  }
+
+  fn get(&self, bins: &Bins, url: &Url) -> Result<Vec<PasteFile>> {
+    let key = try!(engines::get_paste_id(url));
+    let data = try!(engines::download(bins, &format!("http://pastebin.com/raw/{}", key)));
+    if let Some(files) = try!(batch::get_indexed_files(self, bins, &data)) {
+      return Ok(files);
+    }
+    Ok(vec![PasteFile::new(key, data)])
   }
 }
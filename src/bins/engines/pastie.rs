@@ -1,10 +1,10 @@
 use bins::error::*;
 use bins::{Bins, PasteFile};
-use bins::engines::Engine;
+use bins::engines::{self, Engine, Privacy, UploadResult, UploadedFile};
 use hyper::client::Response;
-use bins::engines::batch::{BatchUpload, UploadsBatches, ProducesUrl, ProducesBody};
+use bins::engines::batch::{self, BatchUpload, UploadsBatches, ProducesUrl, ProducesBody};
 use hyper::header::{Headers, ContentType};
-use url::form_urlencoded;
+use url::{form_urlencoded, Url};
 
 pub struct Pastie {
   batch_upload: BatchUpload
@@ -16,6 +16,7 @@ impl Pastie {
     &headers.set(ContentType::form_url_encoded());
     Pastie {
       batch_upload: BatchUpload {
+        service: "pastie",
         url: String::from("http://pastie.org/pastes"),
         headers: headers,
         url_producer: Box::new(PastieUrlProducer { }),
@@ -29,8 +30,16 @@ struct PastieUrlProducer { }
 
 impl ProducesUrl for PastieUrlProducer {
   #[allow(unused_variables)]
-  fn produce_url(&self, bins: &Bins, res: Response, data: String) -> Result<String> {
-    Ok(res.url.as_str().to_owned())
+  fn produce_url(&self, bins: &Bins, file: &PasteFile, res: Response, data: String) -> Result<UploadedFile> {
+    let url = try!(Url::parse(res.url.as_str()).map_err(|e| e.to_string()));
+    let id = try!(engines::get_paste_id(&url));
+    Ok(UploadedFile {
+      name: file.name.clone(),
+      url: url.as_str().to_owned(),
+      raw_url: Some(format!("http://pastie.org/pastes/{}/download", id)),
+      id: Some(id),
+      size: file.data.len()
+    })
   }
 }
 
@@ -43,14 +52,28 @@ impl ProducesBody for PastieBodyProducer {
       form_urlencoded::Serializer::new(String::new())
         .append_pair("paste[body]", &data.data)
         .append_pair("paste[authorization]", "burger")
-        .append_pair("paste[restricted]", if bins.arguments.private { "1" } else { "0" })
+        .append_pair("paste[restricted]", if bins.arguments.privacy > Privacy::Public { "1" } else { "0" })
         .finish()
     )
   }
 }
 
 impl Engine for Pastie {
-  fn upload(&self, bins: &Bins, data: &Vec<PasteFile>) -> Result<String> {
+  fn upload(&self, bins: &Bins, data: &[PasteFile]) -> Result<UploadResult> {
     self.batch_upload.upload(bins, data)
   }
+
+  fn privacy_levels(&self) -> &'static [Privacy] {
+    // restricted pastes are only hidden from listings
+    &[Privacy::Public, Privacy::Unlisted]
+  }
+
+  fn get(&self, bins: &Bins, url: &Url) -> Result<Vec<PasteFile>> {
+    let id = try!(engines::get_paste_id(url));
+    let data = try!(engines::download(bins, &format!("http://pastie.org/pastes/{}/download", id)));
+    if let Some(files) = try!(batch::get_indexed_files(self, bins, &data)) {
+      return Ok(files);
+    }
+    Ok(vec![PasteFile::new(id, data)])
+  }
 }
@@ -1,4 +1,5 @@
 use config;
+use hyper;
 use std::io;
 
 error_chain! {
@@ -27,11 +28,59 @@ error_chain! {
   foreign_links {
     config::error::ConfigError, ConfigError, "configuration error";
     io::Error, IoError, "I/O error";
+    hyper::Error, HyperError, "network error";
   }
 
   // Define additional `ErrorKind` variants. The syntax here is
   // the same as `quick_error!`, but the `from()` and `cause()`
   // syntax is not supported.
   errors {
+    ServerError(status: String) {
+      description("the service had an error")
+      display("the service had an error ({})", status)
+    }
+    InputFile(message: String) {
+      description("a file to paste could not be read")
+      display("{}", message)
+    }
+    InvalidConfiguration(message: String) {
+      description("the configuration is invalid")
+      display("{}", message)
+    }
+  }
+}
+
+/// Exit codes returned by bins, one per class of error.
+pub mod exit_code {
+  /// Everything went fine.
+  pub const SUCCESS: i32 = 0;
+  /// A paste could not be created, fetched or deleted, or another uncategorised error occurred.
+  pub const FAILURE: i32 = 1;
+  /// The command-line arguments could not be parsed. This is the code argparse exits with.
+  pub const USAGE: i32 = 2;
+  /// The configuration file could not be created, read or parsed.
+  pub const CONFIGURATION: i32 = 3;
+  /// A file or stdin could not be read, or a file could not be written.
+  pub const IO: i32 = 4;
+  /// A pastebin service could not be reached or had an error.
+  pub const NETWORK: i32 = 5;
+}
+
+impl Error {
+  pub fn exit_code(&self) -> i32 {
+    match *self.kind() {
+      ErrorKind::ConfigError(..) | ErrorKind::InvalidConfiguration(..) => exit_code::CONFIGURATION,
+      ErrorKind::IoError(..) | ErrorKind::InputFile(..) => exit_code::IO,
+      ErrorKind::HyperError(..) | ErrorKind::ServerError(..) => exit_code::NETWORK,
+      _ => exit_code::FAILURE
+    }
+  }
+
+  /// Checks if this error means the service is down, in which case another one can be tried.
+  pub fn is_unavailable(&self) -> bool {
+    match *self.kind() {
+      ErrorKind::HyperError(..) | ErrorKind::ServerError(..) => true,
+      _ => false
+    }
   }
 }
@@ -0,0 +1,63 @@
+use bins::engines::UploadResult;
+use bins::settings::Settings;
+
+/// Placeholders that describe a single file. Templates using them are filled in once for each file.
+const FILE_PLACEHOLDERS: &'static [&'static str] = &["name", "size"];
+
+/// Gets the template named `format` in the `formats` section, or `format` itself if there is none by that name.
+pub fn get_template<'a>(settings: &'a Settings, format: &'a str) -> &'a str {
+  settings.formats.get(format).map(|t| t.as_ref()).unwrap_or(format)
+}
+
+/// Fills in `template` with the details of an upload. Templates with `{name}` or `{size}` give a line for each file,
+/// where `{url}` and `{raw_url}` are the file's own urls, and others give one line for the whole paste, where
+/// `{service}` is the service the index is on. Templates are filled in for each file of uploads without a url of their
+/// own, made with `--no-index`. Unknown placeholders are left as they are.
+pub fn render(template: &str, result: &UploadResult) -> String {
+  let per_file = result.url.is_empty() || FILE_PLACEHOLDERS.iter().any(|p| template.contains(&format!("{{{}}}", p)));
+  if !per_file {
+    let size = result.files.iter().map(|f| f.size).fold(0, |a, b| a + b);
+    let service = result.index_service.as_ref().unwrap_or(&result.service);
+    return fill(template, result, service, &result.url, result.raw_url.as_ref(), "", size);
+  }
+  result.files.iter()
+    .map(|f| fill(template, result, &result.service, &f.url, f.raw_url.as_ref(), &f.name, f.size))
+    .collect::<Vec<_>>()
+    .join("\n")
+}
+
+/// Fills in `template` for a paste on `service` at `url`, which is either the whole upload or one of its files.
+fn fill(template: &str, result: &UploadResult, service: &str, url: &str, raw_url: Option<&String>, name: &str,
+        size: usize) -> String {
+  let size = size.to_string();
+  let values = [
+    ("url", url),
+    ("raw_url", raw_url.map(|u| u.as_ref()).unwrap_or("")),
+    ("name", name),
+    ("service", service),
+    ("size", size.as_ref()),
+    ("expiry", result.expiry.as_ref().map(|e| e.as_ref()).unwrap_or("never")),
+    ("delete_url", result.delete_url.as_ref().map(|u| u.as_ref()).unwrap_or(""))
+  ];
+  let mut output = String::new();
+  let mut rest = template;
+  while let Some(start) = rest.find('{') {
+    output.push_str(&rest[..start]);
+    let after = &rest[start + 1..];
+    let value = after.find('}').and_then(|end| {
+      values.iter().find(|&&(k, _)| k == &after[..end]).map(|&(_, v)| (v, end))
+    });
+    match value {
+      Some((v, end)) => {
+        output.push_str(v);
+        rest = &after[end + 1..];
+      },
+      None => {
+        output.push('{');
+        rest = after;
+      }
+    }
+  }
+  output.push_str(rest);
+  output
+}
@@ -0,0 +1,64 @@
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::fs::{File, OpenOptions};
+use std::path::PathBuf;
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+use bins::error::*;
+use bins::configuration;
+
+pub struct HistoryEntry {
+  pub time: u64,
+  pub service: String,
+  pub url: String
+}
+
+/// A record of every paste created, kept as tab-separated lines of time, service and url in
+/// `$XDG_DATA_HOME/bins/history`.
+pub struct History {
+  path: PathBuf
+}
+
+impl History {
+  pub fn new() -> Result<Self> {
+    let dir = some_or_err!(
+      configuration::xdg_dir("XDG_DATA_HOME", ".local/share"),
+      "could not get path to the history file".into()
+    );
+    Ok(History { path: dir.join("bins").join("history") })
+  }
+
+  pub fn add(&self, service: &str, url: &str) -> Result<()> {
+    let time = match SystemTime::now().duration_since(UNIX_EPOCH) {
+      Ok(d) => d.as_secs(),
+      Err(_) => 0
+    };
+    if let Some(parent) = self.path.parent() {
+      try!(fs::create_dir_all(parent));
+    }
+    let mut file = try!(OpenOptions::new().create(true).append(true).open(&self.path));
+    try!(writeln!(file, "{}\t{}\t{}", time, service, url));
+    Ok(())
+  }
+
+  pub fn entries(&self) -> Result<Vec<HistoryEntry>> {
+    if !self.path.exists() {
+      return Ok(Vec::new());
+    }
+    let file = try!(File::open(&self.path));
+    let mut entries = Vec::new();
+    for line in BufReader::new(file).lines() {
+      let line = try!(line);
+      let parts = line.splitn(3, '\t').collect::<Vec<_>>();
+      if parts.len() != 3 {
+        continue;
+      }
+      entries.push(HistoryEntry {
+        time: parts[0].parse().unwrap_or(0),
+        service: parts[1].to_owned(),
+        url: parts[2].to_owned()
+      });
+    }
+    Ok(entries)
+  }
+}
@@ -0,0 +1,159 @@
+use bins::error::*;
+use hyper::client::{Client, Response};
+use hyper::header::{Header, HeaderFormat, Headers};
+use hyper::method::Method;
+use regex::Regex;
+use std::io::{self, Read, Write};
+
+/// Form fields and JSON keys holding secrets, which are masked when requests and responses are shown.
+const SECRET_FIELDS: &'static [&'static str] = &["api_dev_key", "api_user_key", "access_token", "device_code"];
+
+/// Headers holding secrets, which are masked when requests and responses are shown.
+const SECRET_HEADERS: &'static [&'static str] = &["authorization", "cookie", "set-cookie"];
+
+thread_local! {
+  /// Matches the secret fields as form fields and as JSON keys. The patterns are the same every time, so they are only
+  /// compiled once.
+  static SECRET_PATTERNS: (Regex, Regex) = {
+    let fields = SECRET_FIELDS.join("|");
+    (Regex::new(&format!(r"(^|&)({})=[^&]*", fields)).unwrap(),
+     Regex::new(&format!(r#""({})"\s*:\s*"[^"]*""#, fields)).unwrap())
+  }
+}
+
+/// Bodies longer than this many bytes are shown as their size.
+const MAX_SHOWN_BODY: usize = 4096;
+
+/// An HTTP request, built up before it is sent so that `--dry-run` and `--verbose` can show it.
+pub struct Request {
+  pub method: Method,
+  pub url: String,
+  pub headers: Headers,
+  pub body: Option<String>
+}
+
+impl Request {
+  pub fn new(method: Method, url: String) -> Self {
+    Request {
+      method: method,
+      url: url,
+      headers: Headers::new(),
+      body: None
+    }
+  }
+
+  pub fn header<H: Header + HeaderFormat>(mut self, header: H) -> Self {
+    self.headers.set(header);
+    self
+  }
+
+  /// Replaces the headers of the request with `headers`.
+  pub fn headers(mut self, headers: Headers) -> Self {
+    self.headers = headers;
+    self
+  }
+
+  pub fn body(mut self, body: String) -> Self {
+    self.body = Some(body);
+    self
+  }
+
+  /// Describes the request like it is sent, with secrets masked.
+  pub fn describe(&self) -> String {
+    let mut text = format!("{} {}\n", self.method, self.url);
+    text.push_str(&describe_headers(&self.headers));
+    if let Some(ref body) = self.body {
+      text.push_str(&format!("\n{}\n", describe_body(body)));
+    }
+    text
+  }
+
+  /// Sends the request and reads the body of the response. With `verbose`, both are shown on stderr.
+  pub fn send(&self, verbose: bool) -> Result<(Response, String)> {
+    if verbose {
+      log("> ", &self.describe());
+    }
+    let client = Client::new();
+    let mut builder = client.request(self.method.clone(), &self.url[..]).headers(self.headers.clone());
+    if let Some(ref body) = self.body {
+      builder = builder.body(&body[..]);
+    }
+    let mut res = try!(builder.send());
+    let mut s = String::from("");
+    try!(res.read_to_string(&mut s).map_err(|e| e.to_string()));
+    if verbose {
+      let mut text = format!("{} {}\n", res.version, res.status);
+      text.push_str(&describe_headers(&res.headers));
+      if !s.is_empty() {
+        text.push_str(&format!("\n{}\n", describe_body(&s)));
+      }
+      log("< ", &text);
+    }
+    Ok((res, s))
+  }
+}
+
+fn describe_headers(headers: &Headers) -> String {
+  let mut text = String::new();
+  for header in headers.iter() {
+    let value = if SECRET_HEADERS.contains(&&header.name().to_lowercase()[..]) {
+      String::from("<hidden>")
+    } else {
+      header.value_string()
+    };
+    text.push_str(&format!("{}: {}\n", header.name(), value));
+  }
+  text
+}
+
+/// Gets `body` with the values of secret form fields and JSON keys masked, or its size if it is too long to show.
+fn describe_body(body: &str) -> String {
+  if body.len() > MAX_SHOWN_BODY {
+    return format!("({} bytes)", body.len());
+  }
+  SECRET_PATTERNS.with(|&(ref form, ref json)| {
+    let body = form.replace_all(body, "${1}${2}=<hidden>");
+    json.replace_all(&body, r#""${1}": "<hidden>""#)
+  })
+}
+
+/// Writes each line of `text` to stderr after `prefix`, like `curl -v`.
+fn log(prefix: &str, text: &str) {
+  let lines = text.lines().map(|l| format!("{}{}\n", prefix, l)).collect::<String>();
+  let _ = io::stderr().write_all(lines.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+  use super::Request;
+  use hyper::header::{Authorization, ContentType};
+  use hyper::method::Method;
+
+  #[test]
+  fn describe_hides_secret_form_fields() {
+    let request = Request::new(Method::Post, String::from("http://pastebin.com/api/api_post.php"))
+      .body(String::from("api_dev_key=abc&api_paste_code=hello&api_user_key=def"));
+    assert_eq!(request.describe(),
+               "POST http://pastebin.com/api/api_post.php\n\n\
+                api_dev_key=<hidden>&api_paste_code=hello&api_user_key=<hidden>\n");
+  }
+
+  #[test]
+  fn describe_hides_secret_headers_and_json_keys() {
+    let request = Request::new(Method::Post, String::from("https://api.github.com/gists"))
+      .header(Authorization(String::from("token abc")))
+      .header(ContentType::json())
+      .body(String::from(r#"{"access_token":"abc","public":false}"#));
+    let description = request.describe();
+    assert!(description.contains("Authorization: <hidden>\n"));
+    assert!(description.contains(r#"{"access_token": "<hidden>","public":false}"#));
+    assert!(!description.contains("abc"));
+  }
+
+  #[test]
+  fn describe_shows_the_size_of_long_bodies() {
+    let request = Request::new(Method::Post, String::from("http://hastebin.com/documents"))
+      .body(String::from_utf8(vec![b'a'; 5000]).unwrap());
+    assert_eq!(request.describe(), "POST http://hastebin.com/documents\n\n(5000 bytes)\n");
+  }
+}
@@ -0,0 +1,217 @@
+use bins::error::*;
+use bins::http::Request;
+use hyper::header::{Accept, ContentType, UserAgent, qitem};
+use hyper::method::Method;
+use hyper::mime::{Mime, TopLevel, SubLevel};
+use rustc_serialize::json::{Json, Object};
+use std::thread;
+use std::time::{Duration, Instant};
+use url::form_urlencoded;
+
+/// A code the user enters at `verification_uri` to authorize bins.
+pub struct DeviceCode {
+  pub device_code: String,
+  pub user_code: String,
+  pub verification_uri: String,
+  pub expires_in: u64,
+  pub interval: u64
+}
+
+/// An access token granted through the device flow, with the scopes it was granted.
+pub struct AccessToken {
+  pub token: String,
+  pub scopes: Vec<String>
+}
+
+/// The OAuth 2.0 device authorization flow as GitHub implements it: ask for a code, have the user enter it in a
+/// browser, then poll until a token is granted.
+pub struct DeviceFlow<'a> {
+  /// The base url of the OAuth endpoints, like `https://github.com`.
+  pub url: &'a str,
+  pub client_id: &'a str,
+  pub scope: &'a str,
+  /// If requests and responses should be shown, for `--verbose`.
+  pub verbose: bool
+}
+
+impl<'a> DeviceFlow<'a> {
+  fn post(&self, path: &str, body: String) -> Result<Object> {
+    let request = Request::new(Method::Post, format!("{}{}", self.url.trim_right_matches('/'), path))
+      .body(body)
+      .header(ContentType::form_url_encoded())
+      .header(Accept(vec![qitem(Mime(TopLevel::Application, SubLevel::Json, vec![]))]))
+      .header(UserAgent(String::from("bins")));
+    let (res, s) = try!(request.send(self.verbose));
+    // servers following RFC 8628 say why polling failed with a 400, so errors in the body are read whatever the status
+    match Json::from_str(&s) {
+      Ok(Json::Object(ref o)) if res.status.is_success() || o.contains_key("error") => Ok(o.clone()),
+      _ if !res.status.is_success() => Err(format!("{} failed ({})", path, res.status).into()),
+      _ => Err("response was not a json object".into())
+    }
+  }
+
+  fn get_str(object: &Object, key: &str) -> Result<String> {
+    let value = some_or_err!(object.get(key), format!("no {} key", key).into());
+    let s = some_or_err!(value.as_string(), format!("{} was not a string", key).into());
+    Ok(s.to_owned())
+  }
+
+  pub fn request_code(&self) -> Result<DeviceCode> {
+    let body = form_urlencoded::Serializer::new(String::new())
+      .append_pair("client_id", self.client_id)
+      .append_pair("scope", self.scope)
+      .finish();
+    let response = try!(self.post("/login/device/code", body));
+    if let Some(e) = response.get("error").and_then(|e| e.as_string()) {
+      return Err(format!("a code could not be requested: {}", e).into());
+    }
+    Ok(DeviceCode {
+      device_code: try!(DeviceFlow::get_str(&response, "device_code")),
+      user_code: try!(DeviceFlow::get_str(&response, "user_code")),
+      verification_uri: try!(DeviceFlow::get_str(&response, "verification_uri")),
+      expires_in: response.get("expires_in").and_then(|j| j.as_u64()).unwrap_or(900),
+      interval: response.get("interval").and_then(|j| j.as_u64()).unwrap_or(5)
+    })
+  }
+
+  /// Polls for the access token until the user authorizes bins, denies it or the code expires.
+  pub fn poll(&self, code: &DeviceCode) -> Result<AccessToken> {
+    let started = Instant::now();
+    let mut interval = code.interval;
+    loop {
+      if started.elapsed() > Duration::from_secs(code.expires_in) {
+        return Err("the code expired before it was entered".into());
+      }
+      thread::sleep(Duration::from_secs(interval));
+      let body = form_urlencoded::Serializer::new(String::new())
+        .append_pair("client_id", self.client_id)
+        .append_pair("device_code", &code.device_code)
+        .append_pair("grant_type", "urn:ietf:params:oauth:grant-type:device_code")
+        .finish();
+      let response = try!(self.post("/login/oauth/access_token", body));
+      match response.get("error").and_then(|e| e.as_string()) {
+        Some("authorization_pending") => continue,
+        Some("slow_down") => {
+          interval = response.get("interval").and_then(|j| j.as_u64()).unwrap_or(interval + 5);
+          continue;
+        },
+        Some("expired_token") => return Err("the code expired before it was entered".into()),
+        Some("access_denied") => return Err("authorization was denied".into()),
+        Some(e) => return Err(format!("authorization failed: {}", e).into()),
+        None => {}
+      }
+      let token = try!(DeviceFlow::get_str(&response, "access_token"));
+      let scopes = response.get("scope")
+        .and_then(|s| s.as_string())
+        .map(|s| s.split(',').map(|x| x.trim().to_owned()).filter(|x| !x.is_empty()).collect())
+        .unwrap_or_else(Vec::new);
+      return Ok(AccessToken { token: token, scopes: scopes });
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::DeviceFlow;
+  use std::io::{Read, Write};
+  use std::net::{TcpListener, TcpStream};
+  use std::thread::{self, JoinHandle};
+
+  /// Reads a request's head and body, going by its `Content-Length`.
+  fn read_request(stream: &mut TcpStream) -> String {
+    let mut data = Vec::new();
+    let mut buffer = [0; 1024];
+    loop {
+      let read = stream.read(&mut buffer).unwrap();
+      data.extend_from_slice(&buffer[..read]);
+      let text = String::from_utf8_lossy(&data).into_owned();
+      if read == 0 {
+        return text;
+      }
+      if let Some(end) = text.find("\r\n\r\n") {
+        let length = text[..end].lines()
+          .filter_map(|l| {
+            let mut parts = l.splitn(2, ':');
+            match (parts.next(), parts.next()) {
+              (Some(name), Some(value)) if name.to_lowercase() == "content-length" => value.trim().parse().ok(),
+              _ => None
+            }
+          })
+          .next()
+          .unwrap_or(0);
+        if data.len() >= end + 4 + length {
+          return text;
+        }
+      }
+    }
+  }
+
+  /// Serves a local OAuth server answering each request with the next of `responses`, and returns the requests it was
+  /// sent when it is joined.
+  fn serve(responses: Vec<(u16, &'static str)>) -> (String, JoinHandle<Vec<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let handle = thread::spawn(move || {
+      let mut requests = Vec::new();
+      for (status, body) in responses {
+        let (mut stream, _) = listener.accept().unwrap();
+        requests.push(read_request(&mut stream));
+        write!(stream, "HTTP/1.1 {} Status\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+                        Connection: close\r\n\r\n{}", status, body.len(), body).unwrap();
+      }
+      requests
+    });
+    (url, handle)
+  }
+
+  fn flow(url: &str) -> DeviceFlow {
+    DeviceFlow {
+      url: url,
+      client_id: "client",
+      scope: "gist",
+      verbose: false
+    }
+  }
+
+  #[test]
+  fn polling_goes_on_while_authorization_is_pending() {
+    let (url, server) = serve(vec![
+      (200, r#"{"device_code": "device", "user_code": "ABCD-1234", "verification_uri": "https://example.com/device",
+                "expires_in": 60, "interval": 0}"#),
+      (400, r#"{"error": "authorization_pending"}"#),
+      (200, r#"{"access_token": "token", "token_type": "bearer", "scope": "gist,repo"}"#)
+    ]);
+    let flow = flow(&url);
+    let code = flow.request_code().unwrap();
+    assert_eq!(code.user_code, "ABCD-1234");
+    assert_eq!(code.verification_uri, "https://example.com/device");
+    let token = flow.poll(&code).unwrap();
+    assert_eq!(token.token, "token");
+    assert_eq!(token.scopes, vec!["gist", "repo"]);
+    let requests = server.join().unwrap();
+    assert!(requests[0].starts_with("POST /login/device/code "));
+    assert!(requests[0].ends_with("client_id=client&scope=gist"));
+    assert!(requests[2].starts_with("POST /login/oauth/access_token "));
+    assert!(requests[2].contains("device_code=device"));
+  }
+
+  #[test]
+  fn denied_authorization_stops_polling() {
+    let (url, server) = serve(vec![
+      (200, r#"{"device_code": "device", "user_code": "ABCD-1234", "verification_uri": "https://example.com/device",
+                "interval": 0}"#),
+      (400, r#"{"error": "access_denied"}"#)
+    ]);
+    let flow = flow(&url);
+    let code = flow.request_code().unwrap();
+    assert!(flow.poll(&code).err().unwrap().to_string().contains("denied"));
+    server.join().unwrap();
+  }
+
+  #[test]
+  fn failures_without_an_error_say_the_status() {
+    let (url, server) = serve(vec![(404, "not found")]);
+    assert!(flow(&url).request_code().err().unwrap().to_string().contains("404"));
+    server.join().unwrap();
+  }
+}
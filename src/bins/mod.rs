@@ -4,55 +4,118 @@ pub mod error;
 pub mod arguments;
 pub mod configuration;
 pub mod engines;
+pub mod history;
+pub mod routes;
+pub mod settings;
+pub mod credentials;
+pub mod login;
+pub mod walk;
+pub mod selection;
+pub mod run;
+pub mod tee;
+pub mod editor;
+pub mod clipboard;
+pub mod format;
+pub mod confirm;
+pub mod http;
 
 extern crate std;
 extern crate config;
 
 use std::io::prelude::*;
 use std::fs::File;
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
 use std::collections::HashMap;
-use config::types::Config;
 use bins::error::*;
-use bins::arguments::Arguments;
-use bins::engines::Engine;
-use bins::engines::gist::Gist;
-use bins::engines::hastebin::Hastebin;
-use bins::engines::pastie::Pastie;
-use bins::engines::pastebin::Pastebin;
+use bins::arguments::{Arguments, Command};
+use bins::configuration::LayeredConfig;
+use bins::settings::Settings;
+use bins::engines::{self, Engine};
+use bins::walk::Walker;
+use bins::selection::{Selection, Selector};
+use url::Url;
 
 #[derive(Clone)]
 pub struct PasteFile {
   pub name: String,
-  pub data: String
+  pub data: String,
+  /// The lines `data` was taken from, if only part of the file is pasted.
+  pub selection: Option<Selection>
 }
 
 impl PasteFile {
-  fn new(name: String, data: String) -> Self {
-    PasteFile { name: name, data: data }
+  pub fn new(name: String, data: String) -> Self {
+    PasteFile { name: name, data: data, selection: None }
   }
 }
 
 pub struct Bins {
-  pub config: Config,
+  pub config: LayeredConfig,
+  pub settings: Settings,
   pub arguments: Arguments
 }
 
 impl Bins {
-  pub fn new(config: Config, arguments: Arguments) -> Self {
+  pub fn new(config: LayeredConfig, settings: Settings, arguments: Arguments) -> Self {
     Bins {
       config: config,
+      settings: settings,
       arguments: arguments
     }
   }
 
   pub fn get_engine(&self) -> Result<Box<Engine>> {
-    match self.arguments.service.to_lowercase().as_ref() {
-      "gist" => Ok(Box::new(Gist::new())),
-      "hastebin" => Ok(Box::new(Hastebin::new())),
-      "pastie" => Ok(Box::new(Pastie::new())),
-      "pastebin" => Ok(Box::new(Pastebin::new())),
-      _ => Err(format!("unknown service \"{}\"", self.arguments.service).into())
+    self.get_engine_by_service(&self.arguments.service)
+  }
+
+  /// Chooses the service to paste `files` to. `--service` or `BINS_SERVICE` take precedence, then the first matching
+  /// route in the configuration file, then `defaults.service`.
+  pub fn get_service(&self, files: &[PasteFile]) -> Result<String> {
+    if !self.arguments.service.is_empty() {
+      return Ok(self.arguments.service.to_lowercase());
+    }
+    for route in &self.settings.routes {
+      if route.matches(files) {
+        return Ok(route.service.to_lowercase());
+      }
+    }
+    match self.settings.defaults.service {
+      Some(ref s) => Ok(s.to_lowercase()),
+      None => Err("no service was specified: use --service, set BINS_SERVICE or set defaults.service".into())
+    }
+  }
+
+  /// Gets the services to paste `files` to. Several services separated by commas, like `gist,hastebin`, mirror the
+  /// paste to each of them.
+  pub fn get_services(&self, files: &[PasteFile]) -> Result<Vec<String>> {
+    let service = try!(self.get_service(files));
+    let services = service.split(',').map(|s| s.trim().to_owned()).filter(|s| !s.is_empty()).collect::<Vec<_>>();
+    if services.is_empty() {
+      return Err(format!("no service was given in \"{}\"", service).into());
+    }
+    Ok(services)
+  }
+
+  pub fn get_url(&self) -> Result<Url> {
+    Url::parse(&self.arguments.url).map_err(|e| format!("invalid url {}: {}", self.arguments.url, e).into())
+  }
+
+  /// Gets the engine for the paste at `url`, either from `--service` or by looking at the url's host.
+  pub fn get_engine_for_url(&self, url: &Url) -> Result<Box<Engine>> {
+    if !self.arguments.service.is_empty() {
+      return self.get_engine();
+    }
+    let service = some_or_err!(
+      engines::get_service_for_url(&self.settings, url),
+      format!("could not tell which service {} is on, use --service", url).into()
+    );
+    self.get_engine_by_service(service)
+  }
+
+  pub fn get_engine_by_service(&self, service: &str) -> Result<Box<Engine>> {
+    match engines::get_engine_by_name(&self.settings, service) {
+      Some(engine) => Ok(engine),
+      None => Err(format!("unknown service \"{}\"", service).into())
     }
   }
 
@@ -60,23 +123,23 @@ impl Bins {
     let path = p.as_ref();
     let name = match path.to_str() {
       Some(s) => s,
-      None => return Err(String::from("file name was not valid unicode").into())
+      None => return Err(ErrorKind::InputFile(String::from("file name was not valid unicode")).into())
     };
     if !path.exists() {
-      return Err(format!("{} does not exist", name).into());
+      return Err(ErrorKind::InputFile(format!("{} does not exist", name)).into());
     }
     if !path.is_file() {
-      return Err(format!("{} is not a file", name).into());
+      return Err(ErrorKind::InputFile(format!("{} is not a file", name)).into());
     }
     let mut file = match File::open(path) {
       Ok(f) => f,
       Err(e) => {
-        return Err(format!("could not open {}: {}", name, e).into());
+        return Err(ErrorKind::InputFile(format!("could not open {}: {}", name, e)).into());
       }
     };
     let mut s = String::new();
     if let Err(e) = file.read_to_string(&mut s) {
-      return Err(format!("could not read {}: {}", name, e).into());
+      return Err(ErrorKind::InputFile(format!("could not read {}: {}", name, e)).into());
     }
     Ok(s)
   }
@@ -85,57 +148,168 @@ impl Bins {
     let path = p.as_ref();
     match self.read_file(path) {
       Ok(s) => {
-        let n = match path.file_name() {
-          Some(x) => x,
-          None => return Err("not a valid file name".into())
-        };
-        Ok(PasteFile::new(n.to_string_lossy().into_owned(), s))
+        let name = try!(self.get_paste_name(path));
+        Ok(PasteFile::new(name, s))
       },
       Err(s) => return Err(s)
     }
   }
 
+  /// Gets the name a file is pasted under: its path relative to the current directory, with `/` as the separator on
+  /// every platform. Files outside the current directory given with an absolute path only keep their file name.
+  fn get_paste_name(&self, path: &Path) -> Result<String> {
+    let current_dir = std::env::current_dir().ok();
+    let relative = current_dir.as_ref().and_then(|d| path.strip_prefix(d).ok()).unwrap_or(path);
+    let parts: Vec<String> = if relative.is_absolute() {
+      relative.file_name().into_iter().map(|n| n.to_string_lossy().into_owned()).collect()
+    } else {
+      relative.components()
+        .filter_map(|c| match c {
+          Component::Normal(n) => Some(n.to_string_lossy().into_owned()),
+          _ => None
+        })
+        .collect()
+    };
+    if parts.is_empty() {
+      return Err("not a valid file name".into());
+    }
+    Ok(parts.join("/"))
+  }
+
   pub fn get_to_paste(&self) -> Result<Vec<PasteFile>> {
     let arguments = &self.arguments;
     let message = &arguments.message;
-    let paste_files: Vec<PasteFile> = if !message.is_empty() {
+    let paste_files: Vec<PasteFile> = if arguments.command == Command::Run {
+      let output = try!(run::run(&arguments.run_command));
+      if arguments.single {
+        vec![output.to_annotated_file()]
+      } else {
+        output.to_paste_files()
+      }
+    } else if arguments.from_clipboard {
+      vec![PasteFile::new(String::from("clipboard"), try!(clipboard::read(&self.settings.clipboard)))]
+    } else if !message.is_empty() {
       vec![PasteFile::new(String::from("message"), message.to_owned())]
     } else if !arguments.files.is_empty() {
-      let files = arguments.files.clone();
-      let results = files.iter()
-        .map(|s| Path::new(s))
-        .map(|p| self.read_file_to_pastefile(p))
-        .map(|r| r.map_err(|e| e.iter().map(|x| x.to_string()).collect::<Vec<_>>().join("\n")))
-        .collect::<Vec<_>>();
-      for res in results.iter().cloned() {
-        if res.is_err() {
-          return Err(res.err().unwrap().into());
-        }
+      let files = try!(self.expand_directories(&arguments.files));
+      let mut pastes = Vec::new();
+      for &(ref p, ref selector) in &files {
+        let file = try!(self.read_file_to_pastefile(p));
+        pastes.push(match *selector {
+          Some(ref s) => try!(s.select(file)),
+          None => file
+        });
       }
-      let mut pastes = results.iter().cloned().map(|r| r.unwrap()).collect::<Vec<_>>();
-      self.handle_duplicate_file_names(&mut pastes);
+      Bins::handle_duplicate_file_names(&mut pastes);
       pastes
+    } else if arguments.tee {
+      vec![PasteFile::new(String::from("stdin"), try!(tee::read_stdin(arguments.keep_on_interrupt)))]
+    } else if editor::stdin_is_tty() {
+      vec![PasteFile::new(String::from("message"), try!(editor::compose(&self.get_editor_header())))]
     } else {
       let mut buffer = String::new();
       if let Err(e) = std::io::stdin().read_to_string(&mut buffer) {
-        return Err(format!("error reading stdin: {}", e).into());
+        return Err(ErrorKind::InputFile(format!("error reading stdin: {}", e)).into());
       }
       vec![PasteFile::new(String::from("stdin"), buffer)]
     };
-    Ok(paste_files)
-  }
-
-  fn handle_duplicate_file_names(&self, pastes: &mut Vec<PasteFile>) {
-    let clone = pastes.clone();
-    let names = clone.iter().map(|p| &p.name).collect::<Vec<_>>();
-    let mut names_map: HashMap<String, i32> = HashMap::new();
-    for mut paste in pastes {
-      let name = paste.name.clone();
-      if names.contains(&&name) {
-        let number = names_map.entry(name.clone()).or_insert(1);
-        paste.name = format!("{}_{}", name, number);
-        *number += 1;
+    Ok(self.redact(paste_files))
+  }
+
+  /// Gets the lines shown in the editor below a new paste, saying where it will be pasted.
+  fn get_editor_header(&self) -> Vec<String> {
+    let placeholder = vec![PasteFile::new(String::from("message"), String::new())];
+    let service = self.get_service(&placeholder).unwrap_or_else(|_| String::from("no service chosen"));
+    vec![
+      format!("Service: {}", service),
+      format!("Privacy: {}", self.arguments.privacy)
+    ]
+  }
+
+  /// Splits line selectors off of `files` and replaces directories with the files in them when `--recursive` is given.
+  fn expand_directories(&self, files: &[String]) -> Result<Vec<(PathBuf, Option<Selector>)>> {
+    let arguments = &self.arguments;
+    let mut paths = Vec::new();
+    let mut walker = None;
+    for argument in files {
+      let (file, selector) = try!(Selector::split(argument));
+      let path = Path::new(&file);
+      if !path.is_dir() {
+        paths.push((path.to_path_buf(), selector));
+        continue;
       }
+      if !arguments.recursive {
+        return Err(format!("{} is a directory (use -r to paste the files in it)", file).into());
+      }
+      if walker.is_none() {
+        walker = Some(try!(Walker::new(&arguments.include, &arguments.exclude, arguments.max_size)));
+      }
+      let found = try!(walker.as_ref().unwrap().walk(path));
+      if found.is_empty() {
+        return Err(format!("{} has no files to paste", file).into());
+      }
+      paths.extend(found.into_iter().map(|p| (p, None)));
     }
+    Ok(paths)
+  }
+
+  /// Replaces matches of the active `redact` patterns with `[redacted]`.
+  fn redact(&self, pastes: Vec<PasteFile>) -> Vec<PasteFile> {
+    pastes.into_iter()
+      .map(|p| {
+        PasteFile {
+          name: p.name,
+          data: self.settings.redact.iter().fold(p.data, |data, r| r.replace_all(&data, "[redacted]")),
+          selection: p.selection
+        }
+      })
+      .collect()
+  }
+
+  /// Numbers files that would be pasted under the same name, like `mod_1.rs` and `mod_2.rs`, keeping extensions.
+  fn handle_duplicate_file_names(pastes: &mut [PasteFile]) {
+    let mut counts: HashMap<String, i32> = HashMap::new();
+    for paste in pastes.iter() {
+      *counts.entry(paste.name.clone()).or_insert(0) += 1;
+    }
+    let mut numbers: HashMap<String, i32> = HashMap::new();
+    for paste in pastes {
+      if counts[&paste.name] < 2 {
+        continue;
+      }
+      let number = numbers.entry(paste.name.clone()).or_insert(1);
+      let file_start = paste.name.rfind('/').map(|i| i + 1).unwrap_or(0);
+      let renamed = match paste.name[file_start..].rfind('.') {
+        Some(i) if i > 0 => {
+          let (stem, extension) = paste.name.split_at(file_start + i);
+          format!("{}_{}{}", stem, number, extension)
+        },
+        _ => format!("{}_{}", paste.name, number)
+      };
+      paste.name = renamed;
+      *number += 1;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{Bins, PasteFile};
+
+  #[test]
+  fn duplicate_names_are_numbered_before_their_extension() {
+    let mut pastes = vec![
+      PasteFile::new(String::from("src/mod.rs"), String::new()),
+      PasteFile::new(String::from("main.rs"), String::new()),
+      PasteFile::new(String::from("src/mod.rs"), String::new()),
+      PasteFile::new(String::from("Makefile"), String::new()),
+      PasteFile::new(String::from("Makefile"), String::new()),
+      PasteFile::new(String::from("a.d/.env"), String::new()),
+      PasteFile::new(String::from("a.d/.env"), String::new())
+    ];
+    Bins::handle_duplicate_file_names(&mut pastes);
+    let names = pastes.iter().map(|p| &p.name[..]).collect::<Vec<_>>();
+    assert_eq!(names, vec!["src/mod_1.rs", "main.rs", "src/mod_2.rs", "Makefile_1", "Makefile_2", "a.d/.env_1",
+                           "a.d/.env_2"]);
   }
 }
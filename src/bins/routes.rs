@@ -0,0 +1,59 @@
+use config::types::Value;
+use glob::Pattern;
+use bins::error::*;
+use bins::PasteFile;
+use bins::configuration::{self, LayeredConfig};
+
+/// A rule choosing the service to paste to when none was given with `--service`.
+pub struct Route {
+  pub service: String,
+  pub pattern: Option<Pattern>,
+  pub larger_than: Option<usize>
+}
+
+impl Route {
+  /// Checks if this route applies to `files`: every file name has to match the pattern, and the files together
+  /// have to be larger than `larger_than` bytes.
+  pub fn matches(&self, files: &[PasteFile]) -> bool {
+    if let Some(ref pattern) = self.pattern {
+      if !files.iter().all(|f| pattern.matches(&f.name)) {
+        return false;
+      }
+    }
+    if let Some(larger_than) = self.larger_than {
+      let size = files.iter().map(|f| f.data.len()).fold(0, |a, b| a + b);
+      if size <= larger_than {
+        return false;
+      }
+    }
+    true
+  }
+}
+
+/// Reads the `routes` list from the configuration, in the order they are defined.
+pub fn get_routes(config: &LayeredConfig) -> Result<Vec<Route>> {
+  let values = match config.lookup("routes") {
+    Some(&Value::List(ref values)) => values,
+    Some(_) => return Err("routes must be a list of groups".into()),
+    None => return Ok(Vec::new())
+  };
+  let mut routes = Vec::new();
+  for (i, value) in values.iter().enumerate() {
+    let service = some_or_err!(configuration::group_str(value, "service"), format!("route {} has no service", i + 1).into());
+    let pattern = match configuration::group_str(value, "pattern") {
+      Some(p) => Some(try!(Pattern::new(p).map_err(|e| format!("route {} has an invalid pattern: {}", i + 1, e)))),
+      None => None
+    };
+    let larger_than = match configuration::group_integer(value, "larger_than") {
+      Some(l) if l < 0 => return Err(format!("route {} has a negative larger_than", i + 1).into()),
+      Some(l) => Some(l as usize),
+      None => None
+    };
+    routes.push(Route {
+      service: service.to_owned(),
+      pattern: pattern,
+      larger_than: larger_than
+    });
+  }
+  Ok(routes)
+}
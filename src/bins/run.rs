@@ -0,0 +1,152 @@
+use bins::error::*;
+use bins::PasteFile;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, PartialEq)]
+enum Stream {
+  Stdout,
+  Stderr
+}
+
+/// What a command printed and how it exited.
+pub struct CommandOutput {
+  command: Vec<String>,
+  status: ExitStatus,
+  duration: Duration,
+  stdout: String,
+  stderr: String,
+  /// Lines of both streams in the order they were printed.
+  combined: Vec<(Stream, String)>
+}
+
+/// Runs `command`, showing its output as it is printed and capturing stdout and stderr both separately and interleaved.
+pub fn run(command: &[String]) -> Result<CommandOutput> {
+  let (program, args) = some_or_err!(command.split_first(), "no command to run was given (use bins run -- <command>)".into());
+  let start = Instant::now();
+  let mut child = try!(
+    Command::new(program)
+      .args(args)
+      .stdin(Stdio::inherit())
+      .stdout(Stdio::piped())
+      .stderr(Stdio::piped())
+      .spawn()
+      .map_err(|e| format!("could not run {}: {}", program, e))
+  );
+  let (sender, receiver) = mpsc::channel();
+  let stdout_pipe = some_or_err!(child.stdout.take(), "could not capture stdout".into());
+  let stderr_pipe = some_or_err!(child.stderr.take(), "could not capture stderr".into());
+  let readers = vec![
+    read_lines(Stream::Stdout, stdout_pipe, sender.clone()),
+    read_lines(Stream::Stderr, stderr_pipe, sender)
+  ];
+  let mut stdout = String::new();
+  let mut stderr = String::new();
+  let mut combined = Vec::new();
+  // the loop ends when both pipes are closed, which is usually when the command exits
+  for (stream, line) in receiver {
+    match stream {
+      Stream::Stdout => {
+        print!("{}", line);
+        stdout.push_str(&line);
+      },
+      Stream::Stderr => {
+        let _ = write!(io::stderr(), "{}", line);
+        stderr.push_str(&line);
+      }
+    }
+    combined.push((stream, line));
+  }
+  for reader in readers {
+    let _ = reader.join();
+  }
+  Ok(CommandOutput {
+    command: command.to_vec(),
+    status: try!(child.wait()),
+    duration: start.elapsed(),
+    stdout: stdout,
+    stderr: stderr,
+    combined: combined
+  })
+}
+
+/// Reads `pipe` line by line on another thread, sending each line with its stream to `sender`.
+fn read_lines<R: io::Read + Send + 'static>(stream: Stream, pipe: R, sender: mpsc::Sender<(Stream, String)>)
+                                           -> thread::JoinHandle<()> {
+  thread::spawn(move || {
+    let mut reader = BufReader::new(pipe);
+    loop {
+      let mut buffer = Vec::new();
+      match reader.read_until(b'\n', &mut buffer) {
+        Ok(0) | Err(_) => break,
+        Ok(_) => {
+          if sender.send((stream, String::from_utf8_lossy(&buffer).into_owned())).is_err() {
+            break;
+          }
+        }
+      }
+    }
+  })
+}
+
+/// Quotes `arg` for a POSIX shell if it has characters the shell would interpret.
+fn quote(arg: &str) -> String {
+  let safe = !arg.is_empty() && arg.chars().all(|c| c.is_alphanumeric() || "-_./=:,+@%".contains(c));
+  if safe {
+    arg.to_owned()
+  } else {
+    format!("'{}'", arg.replace("'", "'\\''"))
+  }
+}
+
+impl CommandOutput {
+  fn command_line(&self) -> String {
+    self.command.iter().map(|a| quote(a)).collect::<Vec<_>>().join(" ")
+  }
+
+  fn describe_status(&self) -> String {
+    match self.status.code() {
+      Some(code) => format!("exit status {}", code),
+      None => String::from("killed by a signal")
+    }
+  }
+
+  fn describe_duration(&self) -> String {
+    format!("{}.{:03}s", self.duration.as_secs(), self.duration.subsec_nanos() / 1000000)
+  }
+
+  fn summary(&self) -> String {
+    format!("$ {}\n{}, took {}\n", self.command_line(), self.describe_status(), self.describe_duration())
+  }
+
+  /// Gets the files to paste: `command.txt` with the command, exit status and duration, `output` with both streams
+  /// interleaved, and `stdout` and `stderr`. Streams the command printed nothing to are left out, since some services
+  /// reject empty files.
+  pub fn to_paste_files(&self) -> Vec<PasteFile> {
+    let combined = self.combined.iter().map(|&(_, ref l)| l.as_ref()).collect::<Vec<&str>>().concat();
+    let mut files = vec![PasteFile::new(String::from("command.txt"), self.summary())];
+    for (name, data) in vec![("output", combined), ("stdout", self.stdout.clone()), ("stderr", self.stderr.clone())] {
+      if !data.is_empty() {
+        files.push(PasteFile::new(String::from(name), data));
+      }
+    }
+    files
+  }
+
+  /// Gets one document with the command, exit status and duration followed by the interleaved output, with lines from
+  /// stderr marked.
+  pub fn to_annotated_file(&self) -> PasteFile {
+    let mut document = self.summary();
+    document.push('\n');
+    for &(stream, ref line) in &self.combined {
+      if stream == Stream::Stderr {
+        document.push_str("[stderr] ");
+      }
+      document.push_str(line);
+    }
+    PasteFile::new(String::from("output.txt"), document)
+  }
+}
@@ -0,0 +1,256 @@
+use bins::error::*;
+use bins::PasteFile;
+use regex::Regex;
+use std::path::Path;
+
+/// Lines of a file that were selected to be pasted instead of the whole file.
+#[derive(Clone)]
+pub struct Selection {
+  /// The first selected line, counting from 1.
+  pub start: usize,
+  /// The last selected line.
+  pub end: usize
+}
+
+enum Start {
+  Line(usize),
+  Pattern(Regex)
+}
+
+enum End {
+  /// Only the first line is selected.
+  Start,
+  Line(usize),
+  /// This many lines after the first one are selected.
+  After(usize)
+}
+
+/// A selector written after a file name, like `file.rs:120-180`, `file.rs:120,+60`, `file.rs:/fn main/,+20` or
+/// `file.rs:/fn main/`. The first line is a line number or the first line matching a regex, and the selection ends at
+/// a line number, a number of lines after the first or the first line itself.
+pub struct Selector {
+  start: Start,
+  end: End
+}
+
+impl Selector {
+  /// Splits `argument` into a file name and a selector. Arguments naming an existing path are never split, so files
+  /// with `:` in their names can still be pasted.
+  pub fn split(argument: &str) -> Result<(String, Option<Selector>)> {
+    if Path::new(argument).exists() {
+      return Ok((argument.to_owned(), None));
+    }
+    for (i, _) in argument.match_indices(':') {
+      let (path, selector) = (&argument[..i], &argument[i + 1..]);
+      if path.is_empty() || !Path::new(path).is_file() {
+        continue;
+      }
+      return Ok((path.to_owned(), Some(try!(Selector::parse(selector)))));
+    }
+    // a selection from a file that doesn't exist would otherwise be taken as the name of another missing file
+    if let Some(i) = argument.rfind(':') {
+      if i > 0 && Selector::parse(&argument[i + 1..]).is_ok() {
+        return Err(ErrorKind::InputFile(format!("file not found: {}", &argument[..i])).into());
+      }
+    }
+    Ok((argument.to_owned(), None))
+  }
+
+  fn parse(text: &str) -> Result<Selector> {
+    let invalid = || -> Error { format!("invalid line selector \"{}\" (use 120-180, 120,+60 or /regex/,+20)", text).into() };
+    let (start, rest) = if text.starts_with('/') {
+      let close = some_or_err!(Selector::find_closing_slash(&text[1..]), invalid()) + 1;
+      let pattern = text[1..close].replace("\\/", "/");
+      let regex = try!(Regex::new(&pattern).map_err(|e| format!("invalid regex in line selector {}: {}", text, e)));
+      (Start::Pattern(regex), &text[close + 1..])
+    } else {
+      let digits = text.find(|c: char| !c.is_digit(10)).unwrap_or(text.len());
+      let line = try!(text[..digits].parse::<usize>().map_err(|_| invalid()));
+      if line == 0 {
+        return Err("line numbers start at 1".into());
+      }
+      (Start::Line(line), &text[digits..])
+    };
+    let end = if rest.is_empty() {
+      End::Start
+    } else if rest.starts_with(",+") {
+      End::After(try!(rest[2..].parse::<usize>().map_err(|_| invalid())))
+    } else if rest.starts_with('-') {
+      End::Line(try!(rest[1..].parse::<usize>().map_err(|_| invalid())))
+    } else {
+      return Err(invalid());
+    };
+    Ok(Selector { start: start, end: end })
+  }
+
+  /// Finds the `/` ending a regex, skipping ones escaped as `\/`.
+  fn find_closing_slash(text: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+      match c {
+        '\\' if !escaped => escaped = true,
+        '/' if !escaped => return Some(i),
+        _ => escaped = false
+      }
+    }
+    None
+  }
+
+  /// Selects lines of `file`, keeping its name and replacing its contents with the selected lines.
+  pub fn select(&self, file: PasteFile) -> Result<PasteFile> {
+    let (start, end, data) = {
+      let lines: Vec<&str> = file.data.lines().collect();
+      let start = match self.start {
+        Start::Line(l) => l,
+        Start::Pattern(ref r) => {
+          some_or_err!(
+            lines.iter().position(|l| r.is_match(l)).map(|i| i + 1),
+            format!("no line of {} matches /{}/", file.name, r.as_str()).into()
+          )
+        }
+      };
+      if start > lines.len() {
+        return Err(format!("{} only has {} lines", file.name, lines.len()).into());
+      }
+      let end = match self.end {
+        End::Start => start,
+        End::Line(l) => l,
+        End::After(n) => start + n
+      };
+      if end < start {
+        return Err(format!("the selection of {} ends before it starts", file.name).into());
+      }
+      let end = ::std::cmp::min(end, lines.len());
+      (start, end, lines[start - 1..end].join("\n") + "\n")
+    };
+    Ok(PasteFile {
+      name: file.name,
+      data: data,
+      selection: Some(Selection { start: start, end: end })
+    })
+  }
+}
+
+/// Gets the line comment marker for files named like `name`, used for the header of selections. Files without line
+/// comments, like JSON and Markdown, and files of unknown types have none.
+fn comment_prefix(name: &str) -> Option<&'static str> {
+  let file_name = name.rsplit('/').next().unwrap_or(name);
+  let extension = file_name.rsplit('.').next().unwrap_or("");
+  match extension {
+    "rs" | "c" | "h" | "cc" | "cpp" | "hpp" | "java" | "js" | "ts" | "go" | "swift" | "kt" | "scala" | "cs" | "php" => {
+      Some("//")
+    },
+    "sql" | "lua" | "hs" => Some("--"),
+    "py" | "rb" | "pl" | "sh" | "bash" | "zsh" | "fish" | "r" | "yml" | "yaml" | "toml" | "cfg" | "conf" | "Makefile" |
+    "Dockerfile" => Some("#"),
+    _ => None
+  }
+}
+
+fn header(prefix: &str, name: &str, selection: &Selection) -> String {
+  format!("{} {}, lines {}-{}", prefix, name, selection.start, selection.end)
+}
+
+/// Adds a comment with the original line numbers to the top of a selection, so it says where it came from.
+pub fn add_header(file: &PasteFile) -> PasteFile {
+  let mut file = file.clone();
+  if let (Some(selection), Some(prefix)) = (file.selection.clone(), comment_prefix(&file.name)) {
+    file.data = format!("{}\n{}", header(prefix, &file.name, &selection), file.data);
+  }
+  file
+}
+
+/// Puts a selection at its original line numbers, so links to lines of the paste are links to the same lines of the
+/// file. The lines before it are blank, except for the header comment on the first one if there is room for it.
+pub fn place_at_original_lines(file: &PasteFile) -> PasteFile {
+  let mut file = file.clone();
+  if let Some(selection) = file.selection.clone() {
+    let mut lines = vec![String::new(); selection.start - 1];
+    if let (Some(first), Some(prefix)) = (lines.first_mut(), comment_prefix(&file.name)) {
+      *first = header(prefix, &file.name, &selection);
+    }
+    file.data = lines.into_iter().map(|l| l + "\n").collect::<String>() + &file.data;
+  }
+  file
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{add_header, place_at_original_lines, Selection, Selector};
+  use bins::PasteFile;
+  use bins::error::ErrorKind;
+
+  const LINES: &'static str = "one\ntwo\nthree\nfour\nfive\n";
+
+  #[test]
+  fn ranges_select_their_lines() {
+    let file = Selector::parse("2-4").unwrap().select(PasteFile::new(String::from("a.rs"), LINES.to_owned())).unwrap();
+    assert_eq!(file.data, "two\nthree\nfour\n");
+    assert_eq!(file.selection.map(|s| (s.start, s.end)), Some((2, 4)));
+  }
+
+  #[test]
+  fn counts_after_the_start_stop_at_the_end_of_the_file() {
+    let file = Selector::parse("4,+9").unwrap().select(PasteFile::new(String::from("a.rs"), LINES.to_owned())).unwrap();
+    assert_eq!(file.data, "four\nfive\n");
+    assert_eq!(file.selection.map(|s| (s.start, s.end)), Some((4, 5)));
+  }
+
+  #[test]
+  fn patterns_start_at_the_first_matching_line() {
+    let selector = Selector::parse(r"/^t\/?hr/").unwrap();
+    let file = selector.select(PasteFile::new(String::from("a.rs"), LINES.to_owned())).unwrap();
+    assert_eq!(file.data, "three\n");
+    let missing = Selector::parse("/six/,+1").unwrap();
+    assert!(missing.select(PasteFile::new(String::from("a.rs"), LINES.to_owned())).is_err());
+  }
+
+  #[test]
+  fn invalid_selectors_are_rejected() {
+    for text in &["", "0", "abc", "5-", "5,20", "5x", "/unclosed", "/(/"] {
+      assert!(Selector::parse(text).is_err(), "{} should not parse", text);
+    }
+    assert!(Selector::parse("4-2").unwrap().select(PasteFile::new(String::from("a.rs"), LINES.to_owned())).is_err());
+    assert!(Selector::parse("6").unwrap().select(PasteFile::new(String::from("a.rs"), LINES.to_owned())).is_err());
+  }
+
+  #[test]
+  fn selections_of_missing_files_are_not_found() {
+    match Selector::split("no/such/file.rs:120-180") {
+      Err(e) => match *e.kind() {
+        ErrorKind::InputFile(ref message) => assert_eq!(message, "file not found: no/such/file.rs"),
+        _ => panic!("the error should be about the file")
+      },
+      Ok(_) => panic!("a missing file should be an error")
+    }
+    assert_eq!(Selector::split("no/such/file.rs").ok().map(|(f, s)| (f, s.is_none())),
+               Some((String::from("no/such/file.rs"), true)));
+  }
+
+  #[test]
+  fn headers_use_the_comments_of_the_file_type() {
+    let selection = Some(Selection { start: 120, end: 180 });
+    let rust = PasteFile {
+      name: String::from("src/main.rs"),
+      data: String::from("fn main() {}\n"),
+      selection: selection.clone()
+    };
+    assert_eq!(add_header(&rust).data, "// src/main.rs, lines 120-180\nfn main() {}\n");
+    let json = PasteFile { name: String::from("package.json"), data: String::from("{}\n"), selection: selection };
+    assert_eq!(add_header(&json).data, "{}\n");
+    let whole = PasteFile::new(String::from("main.py"), String::from("pass\n"));
+    assert_eq!(add_header(&whole).data, "pass\n");
+  }
+
+  #[test]
+  fn selections_can_keep_their_line_numbers() {
+    let file = PasteFile {
+      name: String::from("run.sh"),
+      data: String::from("echo\n"),
+      selection: Some(Selection { start: 4, end: 4 })
+    };
+    assert_eq!(place_at_original_lines(&file).data, "# run.sh, lines 4-4\n\n\necho\n");
+    let first = PasteFile { selection: Some(Selection { start: 1, end: 1 }), ..file };
+    assert_eq!(place_at_original_lines(&first).data, "echo\n");
+  }
+}
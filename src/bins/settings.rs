@@ -0,0 +1,799 @@
+use config::types::{Value, ScalarValue};
+use glob::Pattern;
+use regex::Regex;
+use std::collections::HashMap;
+use std::env;
+use std::process;
+use bins::error::*;
+use bins::configuration::{self, LayeredConfig};
+use bins::routes::{self, Route};
+use bins::credentials::{self, Credential};
+use bins::confirm::Confirm;
+use bins::engines::Privacy;
+use bins::engines::index::IndexFormat;
+use url::Url;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Kind {
+  Boolean,
+  Integer,
+  Float,
+  Str,
+  Group,
+  List
+}
+
+impl Kind {
+  pub fn describe(&self) -> &'static str {
+    match *self {
+      Kind::Boolean => "a boolean",
+      Kind::Integer => "an integer",
+      Kind::Float => "a float",
+      Kind::Str => "a string",
+      Kind::Group => "a group",
+      Kind::List => "a list"
+    }
+  }
+}
+
+/// Every key bins understands and the kind of value it takes. Keys inside the groups of a list, like `routes`, are
+/// named as if the list were a group. A `*` segment matches any name.
+pub const SCHEMA: &'static [(&'static str, Kind)] = &[
+  ("defaults", Kind::Group),
+  ("defaults.private", Kind::Boolean),
+  ("defaults.privacy", Kind::Str),
+  ("defaults.auth", Kind::Boolean),
+  ("defaults.service", Kind::Str),
+  ("defaults.profile", Kind::Str),
+  ("defaults.expiry", Kind::Str),
+  ("defaults.redact", Kind::List),
+  ("defaults.copy", Kind::Boolean),
+  ("defaults.index_service", Kind::Str),
+  ("defaults.fallback", Kind::List),
+  ("defaults.confirm", Kind::Str),
+  ("gist", Kind::Group),
+  ("gist.username", Kind::Str),
+  ("gist.access_token", Kind::Str),
+  ("gist.access_token_command", Kind::Str),
+  ("gist.access_token_file", Kind::Str),
+  ("gist.url", Kind::Str),
+  ("gist.client_id", Kind::Str),
+  ("gist.oauth_url", Kind::Str),
+  ("hastebin", Kind::Group),
+  ("hastebin.url", Kind::Str),
+  ("hastebin.index_format", Kind::Str),
+  ("pastebin", Kind::Group),
+  ("pastebin.api_key", Kind::Str),
+  ("pastebin.api_key_command", Kind::Str),
+  ("pastebin.api_key_file", Kind::Str),
+  ("pastebin.index_format", Kind::Str),
+  ("pastie", Kind::Group),
+  ("pastie.index_format", Kind::Str),
+  ("clipboard", Kind::Group),
+  ("clipboard.paste_command", Kind::Str),
+  ("clipboard.copy_command", Kind::Str),
+  ("formats", Kind::Group),
+  ("formats.*", Kind::Str),
+  ("routes", Kind::List),
+  ("routes.pattern", Kind::Str),
+  ("routes.service", Kind::Str),
+  ("routes.larger_than", Kind::Integer),
+  ("profiles", Kind::Group),
+  ("profiles.*", Kind::Group),
+  ("profiles.*.service", Kind::Str),
+  ("profiles.*.private", Kind::Boolean),
+  ("profiles.*.privacy", Kind::Str),
+  ("profiles.*.auth", Kind::Boolean),
+  ("profiles.*.expiry", Kind::Str),
+  ("profiles.*.redact", Kind::List),
+  ("profiles.*.copy", Kind::Boolean),
+  ("profiles.*.fallback", Kind::List),
+  ("profiles.*.confirm", Kind::Str),
+  ("profiles.*.directories", Kind::List),
+  ("profiles.*.remotes", Kind::List),
+  ("profiles.*.gist", Kind::Group),
+  ("profiles.*.gist.username", Kind::Str),
+  ("profiles.*.gist.access_token", Kind::Str),
+  ("profiles.*.gist.access_token_command", Kind::Str),
+  ("profiles.*.gist.access_token_file", Kind::Str),
+  ("profiles.*.gist.url", Kind::Str),
+  ("profiles.*.gist.client_id", Kind::Str),
+  ("profiles.*.gist.oauth_url", Kind::Str),
+  ("profiles.*.hastebin", Kind::Group),
+  ("profiles.*.hastebin.url", Kind::Str),
+  ("profiles.*.hastebin.index_format", Kind::Str),
+  ("profiles.*.pastebin", Kind::Group),
+  ("profiles.*.pastebin.api_key", Kind::Str),
+  ("profiles.*.pastebin.api_key_command", Kind::Str),
+  ("profiles.*.pastebin.api_key_file", Kind::Str),
+  ("profiles.*.pastebin.index_format", Kind::Str)
+];
+
+/// The expiry times that can be given for `expiry`.
+pub const EXPIRIES: &'static [&'static str] = &["never", "10m", "1h", "1d", "1w", "2w", "1mo", "6mo", "1y"];
+
+/// Keys that can't be set in a project file. Any directory can have one, like a cloned repository, so it isn't trusted
+/// with commands that bins runs, secrets, or the urls secrets are sent to.
+pub const PROJECT_FORBIDDEN: &'static [&'static str] = &[
+  "gist.access_token",
+  "gist.access_token_command",
+  "gist.access_token_file",
+  "gist.url",
+  "gist.oauth_url",
+  "hastebin.url",
+  "pastebin.api_key",
+  "pastebin.api_key_command",
+  "pastebin.api_key_file",
+  "clipboard.paste_command",
+  "clipboard.copy_command",
+  "profiles.*.gist.access_token",
+  "profiles.*.gist.access_token_command",
+  "profiles.*.gist.access_token_file",
+  "profiles.*.gist.url",
+  "profiles.*.gist.oauth_url",
+  "profiles.*.hastebin.url",
+  "profiles.*.pastebin.api_key",
+  "profiles.*.pastebin.api_key_command",
+  "profiles.*.pastebin.api_key_file"
+];
+
+/// Checks if `path` is the key `pattern`, where a `*` segment matches any name.
+fn key_matches(pattern: &str, path: &str) -> bool {
+  let segments = path.split('.').collect::<Vec<_>>();
+  let key_segments = pattern.split('.').collect::<Vec<_>>();
+  key_segments.len() == segments.len() && key_segments.iter().zip(segments.iter()).all(|(a, b)| *a == "*" || a == b)
+}
+
+pub fn get_kind(path: &str) -> Option<Kind> {
+  SCHEMA.iter()
+    .find(|&&(k, _)| key_matches(k, path))
+    .map(|&(_, kind)| kind)
+}
+
+/// A key assigned in a configuration file, with the line it is on.
+pub struct Assignment {
+  pub path: String,
+  pub line: usize,
+  pub kind: Option<Kind>
+}
+
+/// A problem found in a configuration file.
+pub struct Problem {
+  pub line: usize,
+  pub message: String
+}
+
+/// Finds the key assignments in the text of a configuration file. The configuration crate doesn't keep line numbers,
+/// so this does just enough of the parsing to know where each key is and what kind of value it has.
+pub fn scan(text: &str) -> Vec<Assignment> {
+  let chars: Vec<char> = text.chars().collect();
+  let mut assignments = Vec::new();
+  let mut stack: Vec<Option<String>> = Vec::new();
+  let mut pending: Option<String> = None;
+  let mut line = 1;
+  let mut i = 0;
+  while i < chars.len() {
+    let c = chars[i];
+    let next = chars.get(i + 1).cloned();
+    if c == '\n' {
+      line += 1;
+      i += 1;
+    } else if c == '/' && next == Some('*') {
+      i += 2;
+      while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+        if chars[i] == '\n' {
+          line += 1;
+        }
+        i += 1;
+      }
+      i += 2;
+    } else if (c == '/' && next == Some('/')) || c == '#' {
+      while i < chars.len() && chars[i] != '\n' {
+        i += 1;
+      }
+    } else if c == '"' {
+      i += 1;
+      while i < chars.len() && chars[i] != '"' {
+        if chars[i] == '\\' {
+          i += 1;
+        } else if chars[i] == '\n' {
+          line += 1;
+        }
+        i += 1;
+      }
+      i += 1;
+    } else if c == '{' || c == '(' || c == '[' {
+      stack.push(pending.take());
+      i += 1;
+    } else if c == '}' || c == ')' || c == ']' {
+      stack.pop();
+      i += 1;
+    } else if c.is_alphabetic() || c == '_' || c == '*' {
+      let start = i;
+      while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '-' || chars[i] == '*') {
+        i += 1;
+      }
+      let name: String = chars[start..i].iter().cloned().collect();
+      let mut j = i;
+      while j < chars.len() && (chars[j] == ' ' || chars[j] == '\t') {
+        j += 1;
+      }
+      if j < chars.len() && (chars[j] == '=' || chars[j] == ':') {
+        let kind = value_kind(&chars[j + 1..]);
+        let mut path = stack.iter().filter_map(|s| s.clone()).collect::<Vec<_>>();
+        path.push(name.clone());
+        assignments.push(Assignment {
+          path: path.join("."),
+          line: line,
+          kind: kind
+        });
+        if kind == Some(Kind::Group) || kind == Some(Kind::List) {
+          pending = Some(name);
+        }
+        i = j + 1;
+      }
+    } else {
+      i += 1;
+    }
+  }
+  assignments
+}
+
+fn value_kind(chars: &[char]) -> Option<Kind> {
+  let rest = chars.iter().cloned().skip_while(|c| c.is_whitespace()).collect::<String>();
+  let first = match rest.chars().next() {
+    Some(c) => c,
+    None => return None
+  };
+  match first {
+    '"' => Some(Kind::Str),
+    '{' => Some(Kind::Group),
+    '(' | '[' => Some(Kind::List),
+    '0'...'9' | '-' | '+' | '.' => {
+      let token = rest.chars().take_while(|c| c.is_alphanumeric() || *c == '.' || *c == '-' || *c == '+').collect::<String>();
+      if !token.starts_with("0x") && !token.starts_with("0X") && (token.contains('.') || token.contains('e') || token.contains('E')) {
+        Some(Kind::Float)
+      } else {
+        Some(Kind::Integer)
+      }
+    },
+    _ => {
+      let token = rest.chars().take_while(|c| c.is_alphabetic()).collect::<String>().to_lowercase();
+      if token == "true" || token == "false" {
+        Some(Kind::Boolean)
+      } else {
+        None
+      }
+    }
+  }
+}
+
+/// Checks the text of a configuration file against the schema, reporting unknown keys and values of the wrong kind.
+pub fn validate(text: &str) -> Vec<Problem> {
+  let mut problems = Vec::new();
+  for assignment in scan(text) {
+    match get_kind(&assignment.path) {
+      None => problems.push(Problem {
+        line: assignment.line,
+        message: format!("unknown key {}", assignment.path)
+      }),
+      Some(kind) => if assignment.kind != Some(kind) {
+        problems.push(Problem {
+          line: assignment.line,
+          message: format!("{} must be {}", assignment.path, kind.describe())
+        });
+      }
+    }
+  }
+  problems
+}
+
+/// Checks the text of a project file for keys that only the user and system files can set.
+pub fn validate_project(text: &str) -> Vec<Problem> {
+  scan(text).into_iter()
+    .filter(|a| PROJECT_FORBIDDEN.iter().any(|k| key_matches(k, &a.path)))
+    .map(|a| Problem {
+      line: a.line,
+      message: format!("{} can't be set in a project file, only in the user or system file", a.path)
+    })
+    .collect()
+}
+
+pub struct Defaults {
+  /// How hidden pastes should be, from `privacy`, or from `private` in older configuration files.
+  pub privacy: Privacy,
+  /// If services that can't make pastes as hidden as `privacy` should refuse to paste. Only a level set with `privacy`
+  /// is required. Otherwise pastes are made as hidden as the service allows.
+  pub require_privacy: bool,
+  pub auth: bool,
+  pub service: Option<String>,
+  pub expiry: Option<String>,
+  /// If the url of new pastes should be put on the clipboard.
+  pub copy: bool,
+  /// The service to paste the index of multi-file pastes to, if not the one the files are pasted to.
+  pub index_service: Option<String>,
+  /// Services to try in order when the chosen one is unavailable.
+  pub fallback: Vec<String>,
+  /// When to show what is about to be pasted and ask first.
+  pub confirm: Confirm
+}
+
+pub struct GistSettings {
+  pub username: Option<String>,
+  pub access_token: Credential,
+  /// The base url of the GitHub API, which is different for GitHub Enterprise.
+  pub url: String,
+  /// The client id of the OAuth app `bins login gist` authorizes.
+  pub client_id: Option<String>,
+  /// The base url of GitHub's OAuth endpoints, like `https://github.com`.
+  pub oauth_url: String
+}
+
+pub struct HastebinSettings {
+  pub url: String
+}
+
+pub struct PastebinSettings {
+  pub api_key: Credential
+}
+
+/// Commands that read and write the clipboard, used instead of looking for a clipboard tool.
+pub struct ClipboardSettings {
+  pub paste_command: Option<String>,
+  pub copy_command: Option<String>
+}
+
+/// The configuration, read once from every layer and checked. Values from the active profile take the place of the
+/// ones outside of it.
+pub struct Settings {
+  /// The name of the active profile, if any.
+  pub profile: Option<String>,
+  pub defaults: Defaults,
+  pub gist: GistSettings,
+  pub hastebin: HastebinSettings,
+  pub pastebin: PastebinSettings,
+  pub clipboard: ClipboardSettings,
+  /// Named templates for `--format`.
+  pub formats: HashMap<String, String>,
+  /// The index format of each service that pastes files one at a time, if it is set.
+  pub index_formats: HashMap<String, IndexFormat>,
+  pub routes: Vec<Route>,
+  /// Patterns whose matches are replaced with `[redacted]` before pasting.
+  pub redact: Vec<Regex>
+}
+
+/// Reports errors reading the settings as problems with the configuration.
+fn invalid_configuration(e: Error) -> Error {
+  if let ErrorKind::Msg(ref message) = *e.kind() {
+    return ErrorKind::InvalidConfiguration(message.clone()).into();
+  }
+  e
+}
+
+/// Reads settings, looking in `BINS_*` environment variables, then the active profile, then the rest of the
+/// configuration.
+struct Reader<'a> {
+  config: &'a LayeredConfig,
+  name: Option<&'a str>,
+  profile: Option<&'a Value>
+}
+
+impl<'a> Reader<'a> {
+  /// Gets the value of `path` inside the profile, treating `section.key` as nested groups.
+  fn profile_value(&self, path: &str) -> Option<&'a Value> {
+    let mut value = match self.profile {
+      Some(p) => p,
+      None => return None
+    };
+    for segment in path.split('.') {
+      value = match configuration::group_get(value, segment) {
+        Some(v) => v,
+        None => return None
+      };
+    }
+    Some(value)
+  }
+
+  /// Gets the value of `path` inside the profile, unless an environment variable overrides it.
+  fn profile_override(&self, profile_path: &str, path: &str) -> Option<&'a Value> {
+    if self.config.in_environment(path) {
+      return None;
+    }
+    self.profile_value(profile_path)
+  }
+
+  /// Gets a string, treating empty strings as unset.
+  fn get_str(&self, profile_path: &str, path: &str) -> Option<String> {
+    let value = match self.profile_override(profile_path, path) {
+      Some(&Value::Svalue(ScalarValue::Str(ref s))) => Some(s.as_ref()),
+      _ => self.config.lookup_str(path)
+    };
+    match value {
+      Some(s) if !s.is_empty() => Some(s.to_owned()),
+      _ => None
+    }
+  }
+
+  fn get_boolean(&self, profile_path: &str, path: &str, default: bool) -> Result<bool> {
+    if let Some(&Value::Svalue(ScalarValue::Boolean(b))) = self.profile_override(profile_path, path) {
+      return Ok(b);
+    }
+    match self.config.lookup_boolean(path) {
+      Some(b) => Ok(b),
+      None if self.config.lookup_str(path).is_some() => Err(format!("{} must be true or false", path).into()),
+      None => Ok(default)
+    }
+  }
+
+  /// Gets which of `keys` in `defaults` is set with the highest precedence, preferring earlier keys when several are
+  /// set in the same place.
+  fn which_is_set(&self, keys: &[&'static str]) -> Option<&'static str> {
+    let paths = keys.iter().map(|k| format!("defaults.{}", k)).collect::<Vec<_>>();
+    if let Some(i) = paths.iter().position(|p| self.config.in_environment(p)) {
+      return Some(keys[i]);
+    }
+    if let Some(key) = keys.iter().find(|k| self.profile_value(k).is_some()) {
+      return Some(key);
+    }
+    self.config.highest_of(&paths.iter().map(|p| p.as_ref()).collect::<Vec<_>>()).map(|i| keys[i])
+  }
+
+  /// Gets a string that can't be set in a profile, treating empty strings as unset.
+  fn get_global_str(&self, path: &str) -> Option<String> {
+    match self.config.lookup_str(path) {
+      Some(s) if !s.is_empty() => Some(s.to_owned()),
+      _ => None
+    }
+  }
+
+  /// Gets a secret from `path`, `path_command`, `path_file`, the profile's saved secrets or the entry for `host` in
+  /// `~/.netrc`. Secrets are only set in the gist and pastebin sections, which have the same paths in profiles.
+  fn get_credential(&self, path: &str, host: Option<String>) -> Credential {
+    let command = format!("{}_command", path);
+    let file = format!("{}_file", path);
+    Credential::new(
+      path,
+      self.get_str(path, path),
+      self.get_str(&command, &command),
+      self.get_str(&file, &file),
+      credentials::get_store_path(self.name, path),
+      host
+    )
+  }
+
+  /// Describes the effective value of `path` as it would be written in a configuration file, and where it came from.
+  /// Keys of `defaults` are set in a profile without the section, and the others with it.
+  fn describe(&self, path: &str) -> Option<(String, String)> {
+    let profile_path = if path.starts_with("defaults.") { &path["defaults.".len()..] } else { path };
+    if let Some(name) = self.name {
+      let in_profile = get_kind(&format!("profiles.{}.{}", name, profile_path)).is_some();
+      if let (true, Some(value)) = (in_profile, self.profile_override(profile_path, path)) {
+        let origin = match self.config.lookup_with_origin(&format!("profiles.{}", name)) {
+          Some((_, origin)) => format!("profile {} in {}", name, origin),
+          None => format!("profile {}", name)
+        };
+        return Some((configuration::describe_value(path, value), origin));
+      }
+    }
+    self.config.describe(path).map(|(value, origin)| (value, origin.to_string()))
+  }
+
+  fn get_strings(&self, profile_path: &str, path: &str) -> Vec<String> {
+    match self.profile_override(profile_path, path) {
+      Some(value) => configuration::strings(value),
+      None => self.config.lookup_strings(path)
+    }.unwrap_or_else(Vec::new)
+  }
+}
+
+impl Settings {
+  /// Reads the settings with the profile named `profile` active. If no profile is named, the first one whose
+  /// `directories` or `remotes` match the current directory is used if `detect` is set, then `defaults.profile`.
+  /// Detecting runs git, so it is only worth it for commands that paste.
+  pub fn from_config(config: &LayeredConfig, profile: Option<&str>, detect: bool) -> Result<Self> {
+    let profiles = Settings::get_profiles(config);
+    let detected = if profile.is_none() && detect {
+      try!(Settings::detect_profile(&profiles).map_err(invalid_configuration))
+    } else {
+      None
+    };
+    let name = match profile {
+      Some(p) => Some(p.to_owned()),
+      None => match detected {
+        Some(p) => Some(p),
+        None => Reader { config: config, name: None, profile: None }.get_global_str("defaults.profile")
+      }
+    };
+    let active = match name {
+      Some(ref n) => match profiles.iter().find(|&&(ref p, _)| p == n) {
+        Some(&(_, value)) => Some(value),
+        // a missing profile is only a problem with the configuration if the configuration named it
+        None if profile.is_some() => return Err(format!("there is no profile named \"{}\"", n).into()),
+        None => {
+          let message = format!("defaults.profile names \"{}\", but there is no profile with that name", n);
+          return Err(ErrorKind::InvalidConfiguration(message).into());
+        }
+      },
+      None => None
+    };
+    Settings::read(config, name, active).map_err(invalid_configuration)
+  }
+
+  /// Reads the settings with the profile `active`, named `name`, active.
+  fn read(config: &LayeredConfig, name: Option<String>, active: Option<&Value>) -> Result<Self> {
+    let reader = Reader {
+      config: config,
+      name: name.as_ref().map(|n| n.as_ref()),
+      profile: active
+    };
+    // older files set private instead, so it is used if it is set with a higher precedence than privacy, which the
+    // built-in defaults always set
+    let (privacy, require_privacy) = if reader.which_is_set(&["privacy", "private"]) == Some("private") {
+      // private used to mean private or unlisted, which is all most services can do, and services that couldn't
+      // ignored it, so it is still only a preference
+      if try!(reader.get_boolean("private", "defaults.private", true)) {
+        (Privacy::Unlisted, false)
+      } else {
+        (Privacy::Public, false)
+      }
+    } else {
+      match reader.get_str("privacy", "defaults.privacy") {
+        Some(p) => (try!(p.parse::<Privacy>().map_err(|e| format!("privacy: {}", e))), true),
+        None => (Privacy::Unlisted, false)
+      }
+    };
+    let confirm = match reader.get_str("confirm", "defaults.confirm") {
+      Some(c) => try!(c.parse::<Confirm>().map_err(|e| format!("confirm: {}", e))),
+      None => Confirm::Public
+    };
+    let expiry = reader.get_str("expiry", "defaults.expiry");
+    if let Some(ref e) = expiry {
+      if !EXPIRIES.contains(&e.as_ref()) {
+        return Err(format!("expiry must be one of {}", EXPIRIES.join(", ")).into());
+      }
+    }
+    let mut redact = Vec::new();
+    for pattern in reader.get_strings("redact", "defaults.redact") {
+      redact.push(try!(Regex::new(&pattern).map_err(|e| format!("invalid redact pattern {}: {}", pattern, e))));
+    }
+    let mut routes = try!(routes::get_routes(config));
+    if let Some(&Value::Svalue(ScalarValue::Str(ref service))) = reader.profile_override("service", "defaults.service") {
+      // a profile's service wins over routes and defaults.service, but not over --service, BINS_SERVICE or
+      // BINS_DEFAULTS_SERVICE
+      if !service.is_empty() {
+        routes.insert(0, Route { service: service.clone(), pattern: None, larger_than: None });
+      }
+    }
+    let mut index_formats = HashMap::new();
+    let index_format_settings = vec![
+      ("hastebin", reader.get_str("hastebin.index_format", "hastebin.index_format")),
+      ("pastebin", reader.get_str("pastebin.index_format", "pastebin.index_format")),
+      ("pastie", reader.get_global_str("pastie.index_format"))
+    ];
+    for (service, setting) in index_format_settings {
+      if let Some(format) = setting {
+        let format = try!(format.parse::<IndexFormat>().map_err(|e| format!("{}.index_format: {}", service, e)));
+        index_formats.insert(service.to_owned(), format);
+      }
+    }
+    let gist_url = reader.get_str("gist.url", "gist.url").unwrap_or_else(|| String::from("https://api.github.com"));
+    let gist_host = Url::parse(&gist_url).ok().and_then(|u| u.host_str().map(|h| h.to_owned()));
+    Ok(Settings {
+      profile: name.clone(),
+      defaults: Defaults {
+        privacy: privacy,
+        require_privacy: require_privacy,
+        auth: try!(reader.get_boolean("auth", "defaults.auth", true)),
+        service: reader.get_global_str("defaults.service"),
+        expiry: expiry,
+        copy: try!(reader.get_boolean("copy", "defaults.copy", false)),
+        index_service: reader.get_global_str("defaults.index_service"),
+        fallback: reader.get_strings("fallback", "defaults.fallback").iter().map(|s| s.to_lowercase()).collect(),
+        confirm: confirm
+      },
+      gist: GistSettings {
+        username: reader.get_str("gist.username", "gist.username"),
+        access_token: reader.get_credential("gist.access_token", gist_host),
+        client_id: reader.get_str("gist.client_id", "gist.client_id"),
+        oauth_url: reader.get_str("gist.oauth_url", "gist.oauth_url")
+          .unwrap_or_else(|| Settings::get_oauth_url(&gist_url)),
+        url: gist_url
+      },
+      hastebin: HastebinSettings {
+        url: reader.get_str("hastebin.url", "hastebin.url").unwrap_or_else(|| String::from("http://hastebin.com"))
+      },
+      pastebin: PastebinSettings {
+        api_key: reader.get_credential("pastebin.api_key", Some(String::from("pastebin.com")))
+      },
+      clipboard: ClipboardSettings {
+        paste_command: reader.get_global_str("clipboard.paste_command"),
+        copy_command: reader.get_global_str("clipboard.copy_command")
+      },
+      formats: Settings::get_formats(config),
+      index_formats: index_formats,
+      routes: routes,
+      redact: redact
+    })
+  }
+
+  /// Describes the effective value of every setting as it would be written in a configuration file, and where it came
+  /// from, with the values of the active profile in place of the ones they override. Secrets are masked.
+  pub fn describe(&self, config: &LayeredConfig) -> Vec<(String, String, String)> {
+    let profiles = Settings::get_profiles(config);
+    let reader = Reader {
+      config: config,
+      name: self.profile.as_ref().map(|n| n.as_ref()),
+      profile: self.profile.as_ref().and_then(|n| profiles.iter().find(|&&(ref p, _)| p == n)).map(|&(_, v)| v)
+    };
+    let mut keys = SCHEMA.iter()
+      .filter(|&&(k, kind)| kind != Kind::Group && !k.contains('*') && !k.starts_with("routes."))
+      .map(|&(k, _)| k.to_owned())
+      .collect::<Vec<_>>();
+    let mut formats = self.formats.keys().map(|n| format!("formats.{}", n)).collect::<Vec<_>>();
+    formats.sort();
+    keys.extend(formats);
+    keys.into_iter()
+      .filter_map(|k| reader.describe(&k).map(|(value, origin)| (k, value, origin)))
+      .collect()
+  }
+
+  /// Guesses the base url of GitHub's OAuth endpoints from the API url: `https://api.github.com` is served by
+  /// `https://github.com`, and GitHub Enterprise serves both from the same host.
+  fn get_oauth_url(api_url: &str) -> String {
+    match Url::parse(api_url) {
+      Ok(ref u) if u.host_str() == Some("api.github.com") => String::from("https://github.com"),
+      Ok(ref u) => match (u.host_str(), u.port()) {
+        (Some(host), Some(port)) => format!("{}://{}:{}", u.scheme(), host, port),
+        (Some(host), None) => format!("{}://{}", u.scheme(), host),
+        _ => String::from("https://github.com")
+      },
+      Err(_) => String::from("https://github.com")
+    }
+  }
+
+  /// Reads the `formats` section, merging it from every layer.
+  fn get_formats(config: &LayeredConfig) -> HashMap<String, String> {
+    let mut formats = HashMap::new();
+    for group in config.lookup_all("formats") {
+      if let Value::Group(ref settings) = *group {
+        for (name, setting) in settings {
+          if let Value::Svalue(ScalarValue::Str(ref template)) = setting.value {
+            formats.insert(name.clone(), template.clone());
+          }
+        }
+      }
+    }
+    formats
+  }
+
+  /// Gets every profile defined in any layer, in the order they are first written, lowest precedence first. A profile
+  /// defined in more than one layer is taken from the one with the highest precedence.
+  fn get_profiles(config: &LayeredConfig) -> Vec<(String, &Value)> {
+    let mut profiles: Vec<(String, &Value)> = Vec::new();
+    for group in config.lookup_all_groups("profiles") {
+      for (name, value) in group {
+        let existing = profiles.iter().position(|&(ref p, _)| p == name);
+        match existing {
+          Some(i) => profiles[i].1 = value,
+          None => profiles.push((name.clone(), value))
+        }
+      }
+    }
+    profiles
+  }
+
+  /// Finds the profile whose `directories` match the current directory or one of its parents, or whose `remotes`
+  /// match the url of a git remote of the current directory.
+  fn detect_profile(profiles: &[(String, &Value)]) -> Result<Option<String>> {
+    let cwd = try!(env::current_dir());
+    let remotes = Settings::get_git_remotes();
+    for &(ref name, profile) in profiles {
+      for directory in configuration::group_strings(profile, "directories").unwrap_or_else(Vec::new) {
+        let directory = match (directory.starts_with("~/"), configuration::home_dir()) {
+          (true, Some(home)) => home.join(&directory[2..]).to_string_lossy().into_owned(),
+          _ => directory
+        };
+        let pattern = try!(Pattern::new(&directory).map_err(|e| format!("profile {} has an invalid directory: {}", name, e)));
+        let mut dir = cwd.clone();
+        loop {
+          if pattern.matches_path(&dir) {
+            return Ok(Some(name.clone()));
+          }
+          if !dir.pop() {
+            break;
+          }
+        }
+      }
+      for remote in configuration::group_strings(profile, "remotes").unwrap_or_else(Vec::new) {
+        let pattern = try!(Pattern::new(&remote).map_err(|e| format!("profile {} has an invalid remote: {}", name, e)));
+        if remotes.iter().any(|r| pattern.matches(r)) {
+          return Ok(Some(name.clone()));
+        }
+      }
+    }
+    Ok(None)
+  }
+
+  /// Gets the urls of the git remotes of the current directory. Errors mean there are none.
+  fn get_git_remotes() -> Vec<String> {
+    let output = match process::Command::new("git").arg("remote").arg("-v").output() {
+      Ok(o) => o,
+      Err(_) => return Vec::new()
+    };
+    if !output.status.success() {
+      return Vec::new();
+    }
+    String::from_utf8_lossy(&output.stdout)
+      .lines()
+      .filter_map(|l| l.split_whitespace().nth(1).map(|s| s.to_owned()))
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{scan, validate, Kind, Settings};
+  use bins::configuration::{Layer, LayeredConfig, Origin};
+  use std::path::PathBuf;
+
+  #[test]
+  fn profiles_keep_the_order_they_are_written_in() {
+    let user = "profiles = { zeta = { service = \"gist\"; }; alpha = { directories = [\"/\"]; }; };";
+    let project = "profiles = { beta = { directories = [\"/\"]; }; zeta = { directories = [\"/\"]; }; };";
+    let config = LayeredConfig::new(vec![
+      Layer::parse(Origin::User(PathBuf::from("bins.cfg")), user).unwrap(),
+      Layer::parse(Origin::Project(PathBuf::from(".bins.cfg")), project).unwrap()
+    ]);
+    let profiles = Settings::get_profiles(&config);
+    assert_eq!(profiles.iter().map(|&(ref n, _)| &n[..]).collect::<Vec<_>>(), vec!["zeta", "alpha", "beta"]);
+    // zeta is taken from the project file, where it matches every directory
+    assert_eq!(Settings::detect_profile(&profiles).unwrap(), Some(String::from("zeta")));
+  }
+
+  #[test]
+  fn scan_names_nested_keys_with_their_lines() {
+    let text = "defaults = {\n  private = true;\n  service = \"gist\";\n};\nroutes = (\n  { larger_than = 100; }\n);\n";
+    let assignments = scan(text).into_iter().map(|a| (a.path, a.line, a.kind)).collect::<Vec<_>>();
+    assert_eq!(assignments, vec![
+      (String::from("defaults"), 1, Some(Kind::Group)),
+      (String::from("defaults.private"), 2, Some(Kind::Boolean)),
+      (String::from("defaults.service"), 3, Some(Kind::Str)),
+      (String::from("routes"), 5, Some(Kind::List)),
+      (String::from("routes.larger_than"), 6, Some(Kind::Integer))
+    ]);
+  }
+
+  #[test]
+  fn scan_skips_comments() {
+    let text = "# private = 1;\n// auth = 2;\n/* copy = 3;\n expiry = 4; */\ndefaults = { copy = false; };\n";
+    let assignments = scan(text).into_iter().map(|a| (a.path, a.line)).collect::<Vec<_>>();
+    assert_eq!(assignments, vec![(String::from("defaults"), 5), (String::from("defaults.copy"), 5)]);
+  }
+
+  #[test]
+  fn scan_skips_escaped_quotes_in_strings() {
+    let text = "gist = {\n  username = \"a \\\" url = \\\"b\";\n  url = \"c\";\n};\n";
+    let assignments = scan(text).into_iter().map(|a| (a.path, a.line)).collect::<Vec<_>>();
+    assert_eq!(assignments, vec![
+      (String::from("gist"), 1),
+      (String::from("gist.username"), 2),
+      (String::from("gist.url"), 3)
+    ]);
+  }
+
+  #[test]
+  fn validate_reports_unknown_keys_and_wrong_kinds() {
+    let text = "defaults = {\n  private = \"yes\";\n  colour = true;\n};\nprofiles = {\n  work = { auth = false; };\n};\n";
+    let problems = validate(text).into_iter().map(|p| (p.line, p.message)).collect::<Vec<_>>();
+    assert_eq!(problems, vec![
+      (2, String::from("defaults.private must be a boolean")),
+      (3, String::from("unknown key defaults.colour"))
+    ]);
+  }
+
+  #[test]
+  fn validate_tells_floats_from_integers() {
+    assert_eq!(validate("routes = ( { larger_than = 1.5; } );").len(), 1);
+    assert!(validate("routes = ( { larger_than = 0x10; } );").is_empty());
+  }
+}
@@ -0,0 +1,50 @@
+use bins::error::*;
+use std::io::{self, Read, Write};
+
+/// Reads all of stdin, copying it to stdout as it arrives. If `keep_on_interrupt` is true, Ctrl-C doesn't stop bins:
+/// the command writing to the pipe is interrupted instead, and what it printed until then is returned.
+pub fn read_stdin(keep_on_interrupt: bool) -> Result<String> {
+  if keep_on_interrupt {
+    ignore_interrupts(true);
+  }
+  let result = copy_stdin();
+  if keep_on_interrupt {
+    // the upload itself can still be cancelled
+    ignore_interrupts(false);
+  }
+  result
+}
+
+fn copy_stdin() -> Result<String> {
+  let stdin = io::stdin();
+  let mut stdin = stdin.lock();
+  let stdout = io::stdout();
+  let mut buffer = [0; 8192];
+  let mut data = Vec::new();
+  loop {
+    let read = match stdin.read(&mut buffer) {
+      Ok(0) => break,
+      Ok(n) => n,
+      Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+      Err(e) => return Err(ErrorKind::InputFile(format!("error reading stdin: {}", e)).into())
+    };
+    data.extend_from_slice(&buffer[..read]);
+    let mut out = stdout.lock();
+    // a closed stdout shouldn't lose the paste
+    let _ = out.write_all(&buffer[..read]).and_then(|_| out.flush());
+  }
+  Ok(String::from_utf8_lossy(&data).into_owned())
+}
+
+#[cfg(unix)]
+fn ignore_interrupts(ignore: bool) {
+  use libc;
+  let handler = if ignore { libc::SIG_IGN } else { libc::SIG_DFL };
+  unsafe {
+    libc::signal(libc::SIGINT, handler);
+  }
+}
+
+#[cfg(not(unix))]
+#[allow(unused_variables)]
+fn ignore_interrupts(ignore: bool) {}
@@ -0,0 +1,360 @@
+use glob::{Pattern, MatchOptions};
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use bins::error::*;
+
+/// The files in a directory that hold ignore rules, in the order they are read.
+const IGNORE_FILES: &'static [&'static str] = &[".gitignore", ".binsignore"];
+
+/// How many bytes of a file are checked for NUL bytes to decide if it is binary. Files that aren't valid UTF-8 are
+/// binary too.
+const BINARY_CHECK_LENGTH: usize = 8000;
+
+/// A line of a `.gitignore` or `.binsignore`.
+struct IgnoreRule {
+  pattern: Pattern,
+  /// Rules starting with `!` un-ignore what earlier rules ignored.
+  negated: bool,
+  /// Rules ending with `/` only match directories.
+  dir_only: bool,
+  /// Rules with a `/` before the end are matched against the path relative to the ignore file, and others against
+  /// the file name.
+  anchored: bool
+}
+
+/// The rules of the ignore files in one directory.
+struct IgnoreFile {
+  /// The directory the rules apply in, named like the paths being walked.
+  base: PathBuf,
+  /// The path of `base` relative to the directory of the ignore file. This is only set for ignore files in the parents
+  /// of the walked directory.
+  prefix: PathBuf,
+  rules: Vec<IgnoreRule>
+}
+
+impl IgnoreFile {
+  fn read(dir: &Path) -> Result<Option<IgnoreFile>> {
+    let mut rules = Vec::new();
+    for name in IGNORE_FILES {
+      let path = dir.join(name);
+      if !path.is_file() {
+        continue;
+      }
+      let file = try!(File::open(&path));
+      for line in BufReader::new(file).lines() {
+        let line = try!(line);
+        if let Some(rule) = IgnoreFile::parse_rule(line.trim_right()) {
+          rules.push(rule);
+        }
+      }
+    }
+    if rules.is_empty() {
+      return Ok(None);
+    }
+    Ok(Some(IgnoreFile { base: dir.to_path_buf(), prefix: PathBuf::new(), rules: rules }))
+  }
+
+  fn parse_rule(line: &str) -> Option<IgnoreRule> {
+    if line.is_empty() || line.starts_with('#') {
+      return None;
+    }
+    let (negated, line) = if line.starts_with('!') { (true, &line[1..]) } else { (false, line) };
+    let (dir_only, line) = if line.ends_with('/') { (true, &line[..line.len() - 1]) } else { (false, line) };
+    let anchored = line.contains('/');
+    let line = line.trim_left_matches('/');
+    let pattern = match Pattern::new(line) {
+      Ok(p) => p,
+      // git ignores invalid patterns too
+      Err(_) => return None
+    };
+    Some(IgnoreRule {
+      pattern: pattern,
+      negated: negated,
+      dir_only: dir_only,
+      anchored: anchored
+    })
+  }
+
+  /// Checks if this file's rules say anything about `path`: `Some(true)` if it is ignored, `Some(false)` if it is
+  /// explicitly un-ignored.
+  fn matches(&self, path: &Path, is_dir: bool) -> Option<bool> {
+    let relative = match path.strip_prefix(&self.base) {
+      Ok(r) => self.prefix.join(r),
+      Err(_) => return None
+    };
+    let name = match path.file_name() {
+      Some(n) => n.to_string_lossy().into_owned(),
+      None => return None
+    };
+    let options = MatchOptions {
+      case_sensitive: true,
+      require_literal_separator: true,
+      require_literal_leading_dot: false
+    };
+    let mut result = None;
+    for rule in &self.rules {
+      if rule.dir_only && !is_dir {
+        continue;
+      }
+      let matched = if rule.anchored {
+        rule.pattern.matches_path_with(&relative, &options)
+      } else {
+        rule.pattern.matches_with(&name, &options)
+      };
+      if matched {
+        result = Some(!rule.negated);
+      }
+    }
+    result
+  }
+}
+
+/// Finds the files to paste in a directory, like `git ls-files` would, with extra filters.
+pub struct Walker {
+  include: Vec<Pattern>,
+  exclude: Vec<Pattern>,
+  max_size: u64
+}
+
+impl Walker {
+  pub fn new(include: &[String], exclude: &[String], max_size: u64) -> Result<Self> {
+    Ok(Walker {
+      include: try!(Walker::compile(include)),
+      exclude: try!(Walker::compile(exclude)),
+      max_size: max_size
+    })
+  }
+
+  fn compile(patterns: &[String]) -> Result<Vec<Pattern>> {
+    let mut compiled = Vec::new();
+    for pattern in patterns {
+      compiled.push(try!(Pattern::new(pattern).map_err(|e| format!("invalid pattern {}: {}", pattern, e))));
+    }
+    Ok(compiled)
+  }
+
+  /// Gets every file under `root`, sorted, skipping ignored, excluded, binary and oversized files.
+  pub fn walk(&self, root: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut ignores = try!(Walker::read_parent_ignores(root));
+    try!(self.walk_dir(root, root, &mut ignores, &mut files));
+    Ok(files)
+  }
+
+  /// Reads the ignore files in the parents of `root`, up to the root of the git repository it is in, outermost first.
+  /// Nothing is read if `root` isn't inside a repository.
+  fn read_parent_ignores(root: &Path) -> Result<Vec<IgnoreFile>> {
+    let absolute = try!(fs::canonicalize(root));
+    let mut parents = Vec::new();
+    let mut found = absolute.join(".git").exists();
+    let mut dir = absolute.parent();
+    while let Some(d) = dir {
+      if found {
+        break;
+      }
+      parents.push(d);
+      found = d.join(".git").exists();
+      dir = d.parent();
+    }
+    let mut ignores = Vec::new();
+    if !found {
+      return Ok(ignores);
+    }
+    for parent in parents.into_iter().rev() {
+      if let Some(mut ignore) = try!(IgnoreFile::read(parent)) {
+        ignore.prefix = absolute.strip_prefix(parent).map(|p| p.to_path_buf()).unwrap_or_else(|_| PathBuf::new());
+        ignore.base = root.to_path_buf();
+        ignores.push(ignore);
+      }
+    }
+    Ok(ignores)
+  }
+
+  fn walk_dir(&self, root: &Path, dir: &Path, ignores: &mut Vec<IgnoreFile>, files: &mut Vec<PathBuf>) -> Result<()> {
+    let pushed = match try!(IgnoreFile::read(dir)) {
+      Some(ignore) => {
+        ignores.push(ignore);
+        true
+      },
+      None => false
+    };
+    let mut entries = Vec::new();
+    for entry in try!(fs::read_dir(dir)) {
+      entries.push(try!(entry).path());
+    }
+    entries.sort();
+    for path in entries {
+      // like git, symlinks are not followed into the directories they point to
+      let file_type = try!(fs::symlink_metadata(&path)).file_type();
+      if file_type.is_symlink() && path.is_dir() {
+        continue;
+      }
+      let is_dir = file_type.is_dir();
+      if path.file_name().map(|n| n == ".git").unwrap_or(false) || Walker::is_ignored(ignores, &path, is_dir) {
+        continue;
+      }
+      let relative = path.strip_prefix(root).unwrap_or(&path).to_path_buf();
+      if self.exclude.iter().any(|p| Walker::pattern_matches(p, &relative)) {
+        continue;
+      }
+      if is_dir {
+        try!(self.walk_dir(root, &path, ignores, files));
+        continue;
+      }
+      if !self.include.is_empty() && !self.include.iter().any(|p| Walker::pattern_matches(p, &relative)) {
+        continue;
+      }
+      if let Some(reason) = try!(self.skip_reason(&path)) {
+        let _ = writeln!(io::stderr(), "skipping {}: {}", path.to_string_lossy(), reason);
+        continue;
+      }
+      files.push(path);
+    }
+    if pushed {
+      ignores.pop();
+    }
+    Ok(())
+  }
+
+  /// Checks `path` against the ignore files, letting rules in deeper directories override the ones above them.
+  fn is_ignored(ignores: &[IgnoreFile], path: &Path, is_dir: bool) -> bool {
+    ignores.iter().filter_map(|i| i.matches(path, is_dir)).last().unwrap_or(false)
+  }
+
+  /// Patterns without a `/` match the file name anywhere, and others match the path relative to the directory.
+  fn pattern_matches(pattern: &Pattern, relative: &Path) -> bool {
+    if pattern.as_str().contains('/') {
+      return pattern.matches_path(relative);
+    }
+    relative.file_name().map(|n| pattern.matches(&n.to_string_lossy())).unwrap_or(false)
+  }
+
+  fn skip_reason(&self, path: &Path) -> Result<Option<String>> {
+    let metadata = try!(fs::metadata(path));
+    if metadata.len() > self.max_size {
+      return Ok(Some(format!("larger than {} bytes", self.max_size)));
+    }
+    let mut file = try!(File::open(path));
+    let mut data = Vec::new();
+    try!(file.read_to_end(&mut data));
+    let start = &data[..::std::cmp::min(data.len(), BINARY_CHECK_LENGTH)];
+    if start.contains(&0) || ::std::str::from_utf8(&data).is_err() {
+      return Ok(Some(String::from("binary")));
+    }
+    Ok(None)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{IgnoreFile, Walker};
+  use std::env;
+  use std::fs::{self, File};
+  use std::io::Write;
+  use std::path::{Path, PathBuf};
+
+  fn ignore_file(base: &str, rules: &[&str]) -> IgnoreFile {
+    IgnoreFile {
+      base: PathBuf::from(base),
+      prefix: PathBuf::new(),
+      rules: rules.iter().filter_map(|r| IgnoreFile::parse_rule(r)).collect()
+    }
+  }
+
+  /// Makes an empty directory in the temporary directory for a test to write files in.
+  fn test_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("bins-walk-test-{}", name));
+    if dir.exists() {
+      fs::remove_dir_all(&dir).unwrap();
+    }
+    fs::create_dir_all(&dir).unwrap();
+    dir
+  }
+
+  fn write(path: &Path, contents: &str) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    File::create(path).unwrap().write_all(contents.as_bytes()).unwrap();
+  }
+
+  fn walk(root: &Path) -> Vec<String> {
+    Walker::new(&[], &[], 1024).unwrap()
+      .walk(root).unwrap()
+      .iter()
+      .map(|p| p.strip_prefix(root).unwrap().to_string_lossy().into_owned())
+      .collect()
+  }
+
+  #[test]
+  fn comments_and_blank_lines_are_not_rules() {
+    assert!(IgnoreFile::parse_rule("").is_none());
+    assert!(IgnoreFile::parse_rule("# build").is_none());
+    assert!(IgnoreFile::parse_rule("build").is_some());
+  }
+
+  #[test]
+  fn rules_with_a_slash_are_anchored_to_their_directory() {
+    let ignore = ignore_file("root", &["/build", "src/*.o", "*.log"]);
+    assert_eq!(ignore.matches(Path::new("root/build"), false), Some(true));
+    assert_eq!(ignore.matches(Path::new("root/src/build"), false), None);
+    assert_eq!(ignore.matches(Path::new("root/src/a.o"), false), Some(true));
+    assert_eq!(ignore.matches(Path::new("root/lib/src/a.o"), false), None);
+    assert_eq!(ignore.matches(Path::new("root/lib/src/a.log"), false), Some(true));
+  }
+
+  #[test]
+  fn negated_rules_unignore_what_earlier_rules_ignored() {
+    let ignore = ignore_file("root", &["*.log", "!keep.log"]);
+    assert_eq!(ignore.matches(Path::new("root/a.log"), false), Some(true));
+    assert_eq!(ignore.matches(Path::new("root/keep.log"), false), Some(false));
+    assert_eq!(ignore.matches(Path::new("root/a.txt"), false), None);
+  }
+
+  #[test]
+  fn rules_ending_with_a_slash_only_match_directories() {
+    let ignore = ignore_file("root", &["cache/"]);
+    assert_eq!(ignore.matches(Path::new("root/cache"), true), Some(true));
+    assert_eq!(ignore.matches(Path::new("root/cache"), false), None);
+  }
+
+  #[test]
+  fn deeper_ignore_files_override_the_ones_above_them() {
+    let dir = test_dir("nested");
+    write(&dir.join(".gitignore"), "*.log\ncache/\n");
+    write(&dir.join("a.log"), "a\n");
+    write(&dir.join("a.txt"), "a\n");
+    write(&dir.join("cache/b.txt"), "b\n");
+    write(&dir.join("sub/.gitignore"), "!keep.log\n");
+    write(&dir.join("sub/keep.log"), "c\n");
+    write(&dir.join("sub/other.log"), "d\n");
+    assert_eq!(walk(&dir), vec![".gitignore", "a.txt", "sub/.gitignore", "sub/keep.log"]);
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn ignore_files_above_the_walked_directory_apply_inside_a_repository() {
+    let dir = test_dir("parents");
+    fs::create_dir_all(dir.join(".git")).unwrap();
+    write(&dir.join(".gitignore"), "/src/generated.rs\n*.log\n");
+    write(&dir.join("src/generated.rs"), "a\n");
+    write(&dir.join("src/main.rs"), "b\n");
+    write(&dir.join("src/debug.log"), "c\n");
+    assert_eq!(walk(&dir.join("src")), vec!["main.rs"]);
+    // without a repository, nothing above the walked directory is read
+    fs::remove_dir_all(dir.join(".git")).unwrap();
+    assert_eq!(walk(&dir.join("src")), vec!["debug.log", "generated.rs", "main.rs"]);
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn symlinked_directories_are_not_followed() {
+    use std::os::unix::fs::symlink;
+    let dir = test_dir("symlinks");
+    write(&dir.join("real/a.txt"), "a\n");
+    symlink(dir.join("real"), dir.join("link")).unwrap();
+    assert_eq!(walk(&dir), vec!["real/a.txt"]);
+    fs::remove_dir_all(&dir).unwrap();
+  }
+}
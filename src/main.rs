@@ -3,38 +3,393 @@ extern crate argparse;
 extern crate hyper;
 extern crate rustc_serialize;
 extern crate url;
+extern crate glob;
+extern crate regex;
+extern crate libc;
+extern crate sha1;
+extern crate crossbeam;
 #[macro_use]
 extern crate error_chain;
 
 mod bins;
 
 use bins::error::*;
-use bins::Bins;
-use bins::arguments;
+use bins::{Bins, PasteFile};
+use bins::arguments::{self, Arguments, Command};
 use bins::configuration::{BinsConfiguration, Configurable};
-use bins::engines::Engine;
+use bins::engines::{self, UploadResult};
+use bins::clipboard;
+use bins::confirm;
+use bins::format;
+use bins::history::History;
+use bins::settings::Settings;
+use std::fs::{self, File};
+use rustc_serialize::json;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
 
 macro_rules! or_exit {
-    ($expr: expr) => { match $expr { Ok(x) => x, Err(e) => { for err in e.iter() { println!("{}", err); } return 1; } } };
+    ($expr: expr) => { match $expr { Ok(x) => x, Err(e) => { for err in e.iter() { let _ = writeln!(std::io::stderr(), "{}", err); } return e.exit_code(); } } };
 }
 
-fn make_bins() -> Result<Bins> {
-  let configuration = BinsConfiguration::new();
-  let config = try!(configuration.parse_config());
-  let arguments = arguments::get_arguments(&config);
-  Ok(Bins::new(config, arguments))
+fn make_configuration(arguments: &Arguments) -> BinsConfiguration {
+  let path = if arguments.config.is_empty() {
+    None
+  } else {
+    Some(PathBuf::from(&arguments.config))
+  };
+  BinsConfiguration::new(path, arguments.create_config)
+}
+
+fn make_bins(mut arguments: Arguments) -> Result<Bins> {
+  let config = try!(make_configuration(&arguments).parse_config());
+  let profile = if arguments.profile.is_empty() {
+    None
+  } else {
+    Some(arguments.profile.as_ref())
+  };
+  let detect = arguments.command == Command::Upload || arguments.command == Command::Run;
+  let settings = try!(Settings::from_config(&config, profile, detect));
+  try!(arguments.apply_settings(&settings));
+  Ok(Bins::new(config, settings, arguments))
+}
+
+fn upload(bins: &Bins) -> Result<()> {
+  let output_format = &bins.arguments.output_format;
+  if output_format != "url" && output_format != "json" {
+    return Err(format!("unknown output format \"{}\" (use url or json)", output_format).into());
+  }
+  if output_format == "json" && !bins.arguments.format.is_empty() {
+    return Err("--format can't be used with --output json".into());
+  }
+  let to_paste = try!(bins.get_to_paste());
+  let services = try!(bins.get_services(&to_paste));
+  if bins.arguments.dry_run {
+    return dry_run(bins, &services, &to_paste);
+  }
+  try!(confirm::confirm(bins, &services, &to_paste));
+  if services.len() == 1 {
+    let result = try!(upload_with_fallback(bins, &services[0], &services, &to_paste));
+    let printed = try!(render_result(bins, &result));
+    if output_format == "json" {
+      println!("{}", json::as_pretty_json(&result));
+    } else {
+      println!("{}", printed);
+    }
+    add_to_history(&result);
+    copy_to_clipboard(bins, &printed);
+    return Ok(());
+  }
+  let outcomes = try!(mirror(bins, &services, &to_paste));
+  let mut results = Vec::new();
+  let mut printed = Vec::new();
+  let mut failed = 0;
+  for (service, outcome) in services.iter().zip(outcomes) {
+    match outcome {
+      Ok(result) => {
+        // the paste may have been made on a fallback service
+        printed.push(format!("{}: {}", result.service, try!(render_result(bins, &result))));
+        results.push(result);
+      },
+      Err(e) => {
+        failed += 1;
+        let reasons = e.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(": ");
+        let _ = writeln!(std::io::stderr(), "could not paste to {}: {}", service, reasons);
+      }
+    }
+  }
+  if output_format == "json" {
+    println!("{}", json::as_pretty_json(&results));
+  } else if !printed.is_empty() {
+    println!("{}", printed.join("\n"));
+  }
+  for result in &results {
+    add_to_history(result);
+  }
+  copy_to_clipboard(bins, &printed.join("\n"));
+  if failed == services.len() {
+    return Err("the paste could not be made on any service".into());
+  }
+  if failed > 0 && bins.arguments.require_all {
+    return Err(format!("the paste could not be made on {} of {} services", failed, services.len()).into());
+  }
+  Ok(())
+}
+
+/// Goes through uploading `to_paste` to each service, printing the requests instead of sending them. Nothing is
+/// pasted, so nothing is added to the history or copied.
+fn dry_run(bins: &Bins, services: &[String], to_paste: &[PasteFile]) -> Result<()> {
+  for service in services {
+    let engine = try!(bins.get_engine_by_service(service));
+    try!(engines::check_privacy(bins, service, &*engine));
+    try!(engine.upload(bins, to_paste));
+  }
+  Ok(())
+}
+
+/// Uploads `to_paste` to `service`, moving on to the services in `defaults.fallback` while services are unavailable.
+/// Fallbacks in `services`, which are all being pasted to, are left out so the paste isn't made twice on one service.
+fn upload_with_fallback(bins: &Bins, service: &str, services: &[String], to_paste: &[PasteFile])
+                        -> Result<UploadResult> {
+  let mut candidates = vec![service];
+  candidates.extend(bins.settings.defaults.fallback.iter()
+    .map(|s| s.as_ref())
+    .filter(|s| *s != service && !services.iter().any(|m| m == *s)));
+  let mut last_error = None;
+  for (i, candidate) in candidates.into_iter().enumerate() {
+    let engine = try!(bins.get_engine_by_service(candidate));
+    if i == 0 {
+      try!(engines::check_privacy(bins, candidate, &*engine));
+    } else if let Err(e) = engines::check_privacy(bins, candidate, &*engine) {
+      let _ = writeln!(std::io::stderr(), "skipping {}: {}", candidate, e);
+      continue;
+    }
+    match engine.upload(bins, to_paste) {
+      Ok(result) => {
+        if i > 0 {
+          let _ = writeln!(std::io::stderr(), "pasted to {} instead of {}", candidate, service);
+        }
+        return Ok(result);
+      },
+      Err(e) => {
+        if !e.is_unavailable() {
+          return Err(e);
+        }
+        let _ = writeln!(std::io::stderr(), "{} is unavailable: {}", candidate, e);
+        last_error = Some(e);
+      }
+    }
+  }
+  match last_error {
+    Some(e) => Err(e),
+    // the chosen service is never skipped, so this isn't reached
+    None => Err(format!("{} could not be pasted to", service).into())
+  }
+}
+
+/// Uploads `to_paste` to every service in `services` at the same time, falling back like a single upload does, and
+/// returns the outcomes in the same order.
+fn mirror(bins: &Bins, services: &[String], to_paste: &[PasteFile]) -> Result<Vec<Result<UploadResult>>> {
+  // unknown services and services that can't make pastes as hidden as asked are reported before anything is pasted
+  for service in services {
+    let engine = try!(bins.get_engine_by_service(service));
+    try!(engines::check_privacy(bins, service, &*engine));
+  }
+  Ok(crossbeam::scope(|scope| {
+    let handles = services.iter()
+      .map(|service| scope.spawn(move || upload_with_fallback(bins, service, services, to_paste)))
+      .collect::<Vec<_>>();
+    handles.into_iter().map(|h| h.join()).collect()
+  }))
+}
+
+/// Adds a new paste to the history, or the paste of each of its files with `--no-index`. The paste was made, so
+/// failing to record it is only a warning.
+fn add_to_history(result: &UploadResult) {
+  let added = History::new().and_then(|history| {
+    if result.url.is_empty() {
+      for file in &result.files {
+        try!(history.add(&result.service, &file.url));
+      }
+      Ok(())
+    } else {
+      // the url is the index paste's when it was pasted to another service
+      history.add(result.index_service.as_ref().unwrap_or(&result.service), &result.url)
+    }
+  });
+  if let Err(e) = added {
+    let _ = writeln!(std::io::stderr(), "could not add the paste to the history: {}", e);
+  }
+}
+
+/// Gets what to print for a new paste: its url, the urls of its files or its raw urls, or the result of `--format`.
+fn render_result(bins: &Bins, result: &UploadResult) -> Result<String> {
+  if !bins.arguments.format.is_empty() {
+    return Ok(format::render(format::get_template(&bins.settings, &bins.arguments.format), result));
+  }
+  let urls = if bins.arguments.raw {
+    try!(get_raw_urls(result))
+  } else if result.url.is_empty() {
+    result.files.iter().map(|f| f.url.clone()).collect()
+  } else {
+    vec![result.url.clone()]
+  };
+  Ok(urls.join("\n"))
+}
+
+fn copy_to_clipboard(bins: &Bins, text: &str) {
+  if !bins.arguments.copy || text.is_empty() {
+    return;
+  }
+  // the paste was made, so failing to copy its url is only a warning
+  if let Err(e) = clipboard::write(&bins.settings.clipboard, text) {
+    let _ = writeln!(std::io::stderr(), "could not copy the url: {}", e);
+  }
+}
+
+/// Gets the urls of the raw contents of each file in a paste.
+fn get_raw_urls(result: &UploadResult) -> Result<Vec<String>> {
+  let mut urls = Vec::new();
+  for file in &result.files {
+    match file.raw_url {
+      Some(ref url) => urls.push(url.clone()),
+      None => return Err(format!("{} has no raw url for {}", result.service, file.name).into())
+    }
+  }
+  Ok(urls)
+}
+
+fn get(bins: &Bins) -> Result<()> {
+  let url = try!(bins.get_url());
+  let engine = try!(bins.get_engine_for_url(&url));
+  let files = try!(engine.get(bins, &url));
+  if !bins.arguments.output.is_empty() {
+    let output = Path::new(&bins.arguments.output);
+    try!(fs::create_dir_all(output));
+    for file in files {
+      let path = try!(get_output_path(output, &file.name));
+      if let Some(parent) = path.parent() {
+        try!(fs::create_dir_all(parent));
+      }
+      let mut f = try!(File::create(path));
+      try!(f.write_all(file.data.as_bytes()));
+    }
+  } else if files.len() == 1 {
+    print!("{}", files[0].data);
+  } else {
+    for file in files {
+      println!("==> {} <==", file.name);
+      println!("{}", file.data);
+    }
+  }
+  Ok(())
+}
+
+/// Gets where to save a downloaded file named `name`, which can be a relative path, refusing names that would be
+/// saved outside of `output`.
+fn get_output_path(output: &Path, name: &str) -> Result<PathBuf> {
+  let mut path = output.to_path_buf();
+  for part in name.split('/') {
+    match Path::new(part).components().next() {
+      Some(Component::Normal(_)) if Path::new(part).components().count() == 1 => path.push(part),
+      _ => return Err(format!("refusing to save {}: it is not a relative path inside the output directory", name).into())
+    }
+  }
+  Ok(path)
+}
+
+fn delete(bins: &Bins) -> Result<()> {
+  let url = try!(bins.get_url());
+  let engine = try!(bins.get_engine_for_url(&url));
+  engine.delete(bins, &url)
+}
+
+fn login(bins: &Bins) -> Result<()> {
+  let engine = try!(bins.get_engine());
+  engine.login(bins)
+}
+
+fn history(bins: &Bins) -> Result<()> {
+  let entries = try!(try!(History::new()).entries());
+  let skip = if bins.arguments.count == 0 || bins.arguments.count > entries.len() {
+    0
+  } else {
+    entries.len() - bins.arguments.count
+  };
+  for entry in entries.iter().skip(skip) {
+    println!("{}\t{}\t{}", entry.time, entry.service, entry.url);
+  }
+  Ok(())
+}
+
+fn show_config(bins: &Bins) -> Result<()> {
+  let configuration = make_configuration(&bins.arguments);
+  match bins.arguments.action.as_ref() {
+    "path" => match configuration.get_config_path() {
+      Some(p) => println!("{}", p.to_string_lossy()),
+      None => return Err("could not get path to the configuration file".into())
+    },
+    "migrate" => {
+      let (from, to) = try!(configuration.migrate());
+      println!("moved {} to {}", from.to_string_lossy(), to.to_string_lossy());
+    },
+    "show" => {
+      for (key, value, origin) in bins.settings.describe(&bins.config) {
+        if bins.arguments.origin {
+          println!("{} = {}; # {}", key, value, origin);
+        } else {
+          println!("{} = {};", key, value);
+        }
+      }
+    },
+    _ => return Err(format!("unknown config action \"{}\"", bins.arguments.action).into())
+  }
+  Ok(())
+}
+
+/// Reports problems in every configuration file. This runs without loading the configuration, since loading fails on
+/// the problems this is meant to report.
+fn check_config(arguments: &Arguments) -> Result<i32> {
+  let configuration = make_configuration(arguments);
+  let mut code = exit_code::SUCCESS;
+  for path in configuration.get_files() {
+    let problems = try!(configuration.check(&path));
+    if problems.is_empty() {
+      println!("{}: ok", path.to_string_lossy());
+      continue;
+    }
+    code = exit_code::CONFIGURATION;
+    for problem in problems {
+      println!("{}:{}: {}", path.to_string_lossy(), problem.line, problem.message);
+    }
+  }
+  Ok(code)
+}
+
+fn services() -> Result<()> {
+  for service in engines::SERVICES {
+    println!("{}", service);
+  }
+  Ok(())
 }
 
 fn inner() -> i32 {
-  let bins = or_exit!(make_bins());
-  let to_paste = or_exit!(bins.get_to_paste());
-  let engine = or_exit!(bins.get_engine());
-  let url = or_exit!(engine.upload(&bins, &to_paste));
-  println!("{}", url);
-  0
+  let arguments = arguments::get_arguments();
+  if arguments.command == Command::Config && arguments.action == "check" {
+    return or_exit!(check_config(&arguments));
+  }
+  let bins = or_exit!(make_bins(arguments));
+  or_exit!(match bins.arguments.command {
+    Command::Upload => upload(&bins),
+    Command::Get => get(&bins),
+    Command::Delete => delete(&bins),
+    Command::History => history(&bins),
+    Command::Config => show_config(&bins),
+    Command::Services => services(),
+    Command::Login => login(&bins),
+    Command::Run => upload(&bins)
+  });
+  exit_code::SUCCESS
 }
 
 fn main() {
   let exit_code = inner();
   std::process::exit(exit_code);
 }
+
+#[cfg(test)]
+mod tests {
+  use super::get_output_path;
+  use std::path::Path;
+
+  #[test]
+  fn files_are_saved_inside_the_output_directory() {
+    let output = Path::new("out");
+    assert_eq!(get_output_path(output, "src/main.rs").unwrap(), Path::new("out/src/main.rs"));
+    assert!(get_output_path(output, "../main.rs").is_err());
+    assert!(get_output_path(output, "src/../../main.rs").is_err());
+    assert!(get_output_path(output, "/etc/passwd").is_err());
+    assert!(get_output_path(output, "./main.rs").is_err());
+    assert!(get_output_path(output, "src//main.rs").is_err());
+  }
+}